@@ -2,25 +2,45 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-#[macro_use]
-extern crate scan_rules;
-use scan_rules::scan;
-use std::io::BufRead;
+
+pub mod args;
+pub mod lists;
+use args::*;
+use lists::Metric;
 
 fn main() -> Result<()> {
     let stdin = std::io::stdin();
-    let mut list_a = vec![];
-    let mut list_b = vec![];
-    for line in stdin.lock().lines() {
-        let line = line?;
-        scan!(&line;
-            (let num_a: i64,  let num_b: i64) => {
-                list_a.push(num_a);
-                list_b.push(num_b);
-            },
-        )
-        .unwrap();
+    let args: Vec<String> = std::env::args().collect();
+    if has_flag(&args, "--external") {
+        let totals = lists::compare_external(stdin.lock(), lists::DEFAULT_RUN_SIZE)?;
+        println!("{}", totals.distance);
+        return Ok(());
+    }
+
+    let lists = lists::read_lists(stdin.lock())?;
+    if lists.is_empty() {
+        // no input at all, so two empty lists
+        println!("0");
+        return Ok(());
     }
+    let metric: Option<Metric> = parse_arg(&args, "--metric")?;
+    if has_flag(&args, "--matrix") || lists.len() != 2 {
+        let matrix = lists::Matrix::new(&lists, metric.unwrap_or(Metric::Distance))?;
+        if has_flag(&args, "--json") {
+            matrix.print_json()?;
+        } else {
+            matrix.print_table();
+        }
+        return Ok(());
+    }
+    if let Some(metric) = metric {
+        let top_k = parse_arg(&args, "--top")?;
+        let report = lists::Report::new(&lists[0], &lists[1])?;
+        report.print(metric, top_k.unwrap_or(0), has_flag(&args, "--pairing"));
+        return Ok(());
+    }
+
+    let [mut list_a, mut list_b] = <[Vec<i64>; 2]>::try_from(lists).unwrap();
     list_a.sort();
     list_b.sort();
     let mut sum = 0;
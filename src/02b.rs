@@ -2,89 +2,49 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-use std::cmp::Ordering;
 use std::io::BufRead;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
-enum Order {
-    Decreasing,
-    Increasing,
-}
-
-fn check(seq: &[i64], dampen_limit: u64) -> bool {
-    let (mut prev, rest) = seq.split_first().unwrap();
-
-    let mut dampen_count = 0;
-    let mut order = None;
-
-    for n in rest {
-        if (n - prev).abs() > 3 {
-            dampen_count += 1;
-            continue;
-        }
-
-        match n.cmp(prev) {
-            Ordering::Greater => {
-                if order == Some(Order::Increasing) || order.is_none() {
-                    order = Some(Order::Increasing);
-                } else {
-                    dampen_count += 1;
-                    continue;
-                }
-            }
-            Ordering::Less => {
-                if order == Some(Order::Decreasing) || order.is_none() {
-                    order = Some(Order::Decreasing);
-                } else {
-                    dampen_count += 1;
-                    continue;
-                }
-            }
-            Ordering::Equal => {
-                dampen_count += 1;
-                continue;
-            }
-        }
-
-        prev = n;
-    }
-    dampen_count <= dampen_limit
-}
+pub mod args;
+#[cfg(test)]
+pub mod lcg;
+pub mod reports;
+use args::has_flag;
+use reports::SafetyPolicy;
 
 fn main() -> Result<()> {
     let stdin = std::io::stdin();
+    let args: Vec<String> = std::env::args().collect();
+    let policy = SafetyPolicy {
+        dampener: 1,
+        ..Default::default()
+    }
+    .with_args(&args)?;
+
+    let diagnose = has_flag(&args, "--diagnose");
 
     let mut sum = 0;
-    for line in stdin.lock().lines() {
+    let mut diagnoses = vec![];
+    for (i, line) in stdin.lock().lines().enumerate() {
         let line = line?;
-        let mut seq: Vec<i64> = line
+        if line.trim().is_empty() {
+            continue;
+        }
+        let seq: Vec<i64> = line
             .split_whitespace()
-            .map(|n| n.parse().unwrap())
-            .rev()
-            .collect();
+            .map(|n| n.parse())
+            .collect::<Result<_, _>>()?;
 
-        if check(&seq, 1) {
+        if diagnose {
+            diagnoses.extend(policy.diagnose(i + 1, &seq));
+        } else if policy.is_safe(&seq) {
             sum += 1;
-        } else if seq.len() > 1 {
-            let (_first, rest) = seq.split_first().unwrap();
-            // edge case 1: we don't allow option to skip the first element in
-            // the check function let's run another check without it but with
-            // lower dampen limit
-            if check(rest, 0) {
-                sum += 1;
-            } else {
-                // edge case 2: even if first two element are making safe
-                // sequence we may want to skip the second one so avoid forcing
-                // the ascending/descending order for the rest of sequence
-                seq[1] = seq[0];
-                let (_first, rest) = seq.split_first().unwrap();
-                if check(rest, 0) {
-                    sum += 1;
-                }
-            }
         }
     }
 
+    if diagnose {
+        return reports::print_diagnoses(&diagnoses, has_flag(&args, "--json"));
+    }
+
     println!("{sum}");
     Ok(())
 }
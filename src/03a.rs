@@ -2,21 +2,42 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-use regex::Regex;
-use std::io::BufRead;
+use std::io::Read;
 
-fn main() -> Result<()> {
-    let stdin = std::io::stdin();
+pub mod args;
+pub mod corrupted;
+#[cfg(test)]
+pub mod lcg;
+use args::has_flag;
+use corrupted::{Format, Mul, Registry};
 
-    let re = Regex::new(r"mul\((\d+),(\d+)\)").unwrap();
-    let mut sum: i64 = 0;
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let mut registry = Registry::new();
+    registry.register(Box::new(Mul));
 
-    for line in stdin.lock().lines() {
-        let line = line?;
-        for caps in re.captures_iter(&line) {
-            sum += caps[1].parse::<i64>()? * caps[2].parse::<i64>()?;
-        }
+    if has_flag(&args, "--report") {
+        let mut input = vec![];
+        std::io::stdin().lock().read_to_end(&mut input)?;
+        let candidates = registry.scan(&input);
+        registry.print_candidates(&input, &candidates);
+        println!("{}", registry.run(&candidates).sum);
+        return Ok(());
     }
-    println!("{}", sum);
+    if has_flag(&args, "--annotate") {
+        let mut input = vec![];
+        std::io::stdin().lock().read_to_end(&mut input)?;
+        let candidates = registry.scan(&input);
+        let format = if has_flag(&args, "--html") {
+            Format::Html
+        } else {
+            Format::Ansi
+        };
+        print!("{}", registry.annotate(&input, &candidates, format));
+        return Ok(());
+    }
+
+    let state = registry.run_reader(std::io::stdin().lock())?;
+    println!("{}", state.sum);
     Ok(())
 }
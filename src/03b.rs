@@ -2,33 +2,41 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-use regex::Regex;
-use std::io::BufRead;
+use std::io::Read;
 
-fn main() -> Result<()> {
-    let stdin = std::io::stdin();
-
-    let re = Regex::new(r"(?P<mul>mul\((\d+),(\d+)\))|(?P<dont>don't\(\))|(?P<do>do\(\))").unwrap();
-    let mut sum: i64 = 0;
+pub mod args;
+pub mod corrupted;
+#[cfg(test)]
+pub mod lcg;
+use args::has_flag;
+use corrupted::{Format, Registry};
 
-    let mut toggle = true;
-    for line in stdin.lock().lines() {
-        let line = line?;
-        for caps in re.captures_iter(&line) {
-            if let Some(_link_match) = caps.name("mul") {
-                if toggle {
-                    sum += caps[2].parse::<i64>()? * caps[3].parse::<i64>()?;
-                }
-            }
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let registry = Registry::standard();
 
-            if let Some(_link_match) = caps.name("do") {
-                toggle = true;
-            }
-            if let Some(_link_match) = caps.name("dont") {
-                toggle = false;
-            }
-        }
+    if has_flag(&args, "--report") {
+        let mut input = vec![];
+        std::io::stdin().lock().read_to_end(&mut input)?;
+        let candidates = registry.scan(&input);
+        registry.print_candidates(&input, &candidates);
+        println!("{}", registry.run(&candidates).sum);
+        return Ok(());
     }
-    println!("{}", sum);
+    if has_flag(&args, "--annotate") {
+        let mut input = vec![];
+        std::io::stdin().lock().read_to_end(&mut input)?;
+        let candidates = registry.scan(&input);
+        let format = if has_flag(&args, "--html") {
+            Format::Html
+        } else {
+            Format::Ansi
+        };
+        print!("{}", registry.annotate(&input, &candidates, format));
+        return Ok(());
+    }
+
+    let state = registry.run_reader(std::io::stdin().lock())?;
+    println!("{}", state.sum);
     Ok(())
 }
@@ -1,59 +1,49 @@
 // Advent of Code 2024
 // (c) 2024 Mateusz Kwapich
 
-use anyhow::Result;
-use itertools::Itertools;
+use anyhow::{Context, Result};
 use std::io::BufRead;
 
+pub mod args;
+pub mod map;
+pub mod wordsearch;
+use args::*;
+use map::*;
+use wordsearch::*;
+
 fn main() -> Result<()> {
-    let stdin = std::io::stdin();
+    let args: Vec<String> = std::env::args().collect();
+    let mut words: Vec<String> = arg_values(&args, "--word").map(String::from).collect();
+    if let Some(path) = arg_value(&args, "--dict") {
+        let dict = std::fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+        words.extend(dict.split_whitespace().map(String::from));
+    }
+    if words.is_empty() {
+        words.push("XMAS".to_string());
+    }
+    let list = has_flag(&args, "--list");
 
-    let mut lines = vec![];
-    let mut horizontal = String::new();
+    let stdin = std::io::stdin();
+    let mut tiles = vec![];
     for line in stdin.lock().lines() {
         let line = line?;
-        lines.push(line.chars().collect_vec());
-        horizontal.push_str(&line);
-        horizontal.push('\n');
+        tiles.push(line.bytes().collect());
     }
-    let h = lines.len();
-    let w = lines[0].len();
+    let map = Map::new(tiles);
 
-    let mut vertical = String::new();
-    for i in 0..h {
-        for line in lines.iter() {
-            vertical.push(line[i]);
+    let dictionary = Dictionary::new(words)?;
+    let matches = dictionary.search(&map);
+    let multiple = dictionary.words().len() > 1;
+    for (word, matches) in dictionary.words().iter().zip(matches.iter()) {
+        if multiple {
+            println!("{word}: {}", matches.len());
         }
-        vertical.push('\n');
-    }
-
-    let mut diag1 = String::new();
-    for i in 0..(h + w - 1) {
-        for j in (0..=i).rev() {
-            let c = lines.get(j).map(|l| l.get(i - j));
-            if let Some(Some(c)) = c {
-                diag1.push(*c);
+        if list {
+            for m in matches.iter() {
+                println!("  {},{} {:?}", m.start.x, m.start.y, m.heading);
             }
         }
-        diag1.push('\n');
-    }
-
-    let mut diag2 = String::new();
-    for i in 0..(h + w - 1) {
-        for j in (0..=i).rev() {
-            let c = lines.get(j).map(|l| l.get(w - i + j));
-            if let Some(Some(c)) = c {
-                diag2.push(*c);
-            }
-        }
-        diag2.push('\n');
-    }
-
-    let mut sum = 0;
-    for s in [horizontal, vertical, diag1, diag2] {
-        sum += s.matches("XMAS").count();
-        sum += s.matches("SAMX").count();
     }
-    println!("{sum}");
+    println!("{}", matches.iter().map(|m| m.len()).sum::<usize>());
     Ok(())
 }
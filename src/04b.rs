@@ -2,45 +2,41 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-use itertools::Itertools;
 use std::io::BufRead;
 
+pub mod args;
+pub mod map;
+pub mod wordsearch;
+use args::has_flag;
+use map::*;
+use wordsearch::*;
+
+const X_MAS: &str = "\
+M.S
+.A.
+M.S";
+
 fn main() -> Result<()> {
-    let stdin = std::io::stdin();
+    let args: Vec<String> = std::env::args().collect();
+    let list = has_flag(&args, "--list");
 
-    let mut lines = vec![];
+    let stdin = std::io::stdin();
+    let mut tiles = vec![];
     for line in stdin.lock().lines() {
         let line = line?;
-        lines.push(line.chars().collect_vec());
+        tiles.push(line.bytes().collect());
     }
+    let map = Map::new(tiles);
 
-    let h = lines.len();
-    let w = lines[0].len();
-
-    let mut sum = 0;
-    for i in 1..(h - 1) {
-        for j in 1..(w - 1) {
-            let mut cnt = 0;
-            if lines[i][j] == 'A' {
-                if lines[i - 1][j - 1] == 'M' || lines[i + 1][j + 1] == 'M' {
-                    cnt += 1;
-                }
-                if lines[i - 1][j - 1] == 'S' || lines[i + 1][j + 1] == 'S' {
-                    cnt += 1;
-                }
-                if lines[i - 1][j + 1] == 'M' || lines[i + 1][j - 1] == 'M' {
-                    cnt += 1;
-                }
-                if lines[i - 1][j + 1] == 'S' || lines[i + 1][j - 1] == 'S' {
-                    cnt += 1;
-                }
-            }
-            if cnt == 4 {
-                sum += 1;
-            }
+    let matches = find_stencil(&map, &Stencil::parse(X_MAS, b'.'), false);
+    if list {
+        for m in matches.iter() {
+            println!(
+                "{},{} rotated {}",
+                m.top_left.x, m.top_left.y, m.orientation.rotation
+            );
         }
     }
-
-    println!("{sum}");
+    println!("{}", matches.len());
     Ok(())
 }
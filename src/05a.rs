@@ -2,25 +2,24 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-use std::{collections::BTreeSet, io::BufRead};
+use std::io::BufRead;
 #[macro_use]
 extern crate scan_rules;
 use scan_rules::scan;
 
-fn validate(rules: &BTreeSet<(i64, i64)>, pages: &[i64]) -> bool {
-    for w in pages.windows(2) {
-        let (a, b) = (w[0], w[1]);
-        if rules.contains(&(b, a)) {
-            return false;
-        }
-    }
-    true
-}
+pub mod args;
+#[cfg(test)]
+pub mod lcg;
+pub mod ordering;
+use args::has_flag;
+use ordering::*;
 
 fn main() -> Result<()> {
     let stdin = std::io::stdin();
+    let args: Vec<String> = std::env::args().collect();
+    let explain_updates = has_flag(&args, "--explain");
 
-    let mut rules = BTreeSet::new();
+    let mut rules = PageOrdering::new();
     for line in stdin.lock().lines() {
         let line = line?;
         if line.is_empty() {
@@ -29,24 +28,38 @@ fn main() -> Result<()> {
 
         scan!(&line;
             (let num_a: i64, "|", let num_b: i64) => {
-                rules.insert((num_a, num_b));
+                rules.add(num_a, num_b);
             },
         )
         .unwrap();
     }
 
+    if has_flag(&args, "--serve") {
+        return serve(&mut rules, stdin.lock(), std::io::stdout().lock());
+    }
+
     let mut sum = 0;
-    for line in stdin.lock().lines() {
+    let mut explanations = vec![];
+    for (i, line) in stdin.lock().lines().enumerate() {
         let line = line?;
         let pages = line
             .split(',')
             .map(|n| n.parse::<i64>())
             .collect::<Result<Vec<_>, std::num::ParseIntError>>()?;
 
-        if validate(&rules, &pages) {
+        if explain_updates {
+            explanations.extend(explain(&rules, i + 1, &pages));
+            continue;
+        }
+
+        if rules.is_valid(&pages) {
             sum += pages[pages.len() / 2];
         }
     }
+    if explain_updates {
+        return print_explanations(&explanations, has_flag(&args, "--json"));
+    }
+
     println!("{sum}");
     Ok(())
 }
@@ -1,26 +1,25 @@
 // Advent of Code 2024
 // (c) 2024 Mateusz Kwapich
 
-use anyhow::Result;
-use std::{cmp::Ordering, collections::BTreeSet, io::BufRead};
+use anyhow::{Result, bail};
+use std::io::BufRead;
 #[macro_use]
 extern crate scan_rules;
 use scan_rules::scan;
 
-fn validate(rules: &BTreeSet<(i64, i64)>, pages: &[i64]) -> bool {
-    for w in pages.windows(2) {
-        let (a, b) = (w[0], w[1]);
-        if rules.contains(&(b, a)) {
-            return false;
-        }
-    }
-    true
-}
+pub mod args;
+#[cfg(test)]
+pub mod lcg;
+pub mod ordering;
+use args::has_flag;
+use ordering::*;
 
 fn main() -> Result<()> {
     let stdin = std::io::stdin();
+    let args: Vec<String> = std::env::args().collect();
+    let explain_updates = has_flag(&args, "--explain");
 
-    let mut rules = BTreeSet::new();
+    let mut rules = PageOrdering::new();
     for line in stdin.lock().lines() {
         let line = line?;
         if line.is_empty() {
@@ -29,36 +28,53 @@ fn main() -> Result<()> {
 
         scan!(&line;
             (let num_a: i64, "|", let num_b: i64) => {
-                rules.insert((num_a, num_b));
+                rules.add(num_a, num_b);
             },
         )
         .unwrap();
     }
 
+    if has_flag(&args, "--serve") {
+        return serve(&mut rules, stdin.lock(), std::io::stdout().lock());
+    }
+
     let mut sum = 0;
-    for line in stdin.lock().lines() {
+    let mut explanations = vec![];
+    for (i, line) in stdin.lock().lines().enumerate() {
         let line = line?;
-        let mut pages = line
+        let pages = line
             .split(',')
             .map(|n| n.parse::<i64>())
             .collect::<Result<Vec<_>, std::num::ParseIntError>>()?;
 
-        if validate(&rules, &pages) {
-            // skip over correctly validating pairs
+        if explain_updates {
+            explanations.extend(explain(&rules, i + 1, &pages));
             continue;
         }
 
-        pages.sort_by(|a, b| {
-            if rules.contains(&(*a, *b)) {
-                return Ordering::Less;
+        // valid updates are ordered too, so that cycles and ambiguity in
+        // their rules get reported
+        let order = match topological_order(&rules, &pages) {
+            Order::Unique(order) => order,
+            Order::Ambiguous { order, choice } => {
+                eprintln!(
+                    "{line}: no rule orders {} and {}, picked {order:?}",
+                    choice.0, choice.1
+                );
+                order
             }
-            if rules.contains(&(*b, *a)) {
-                return Ordering::Greater;
-            }
-            a.cmp(b)
-        });
-        sum += pages[pages.len() / 2];
+            Order::Cycle(cycle) => bail!("{line}: rules form a cycle {cycle:?}"),
+        };
+        if order == pages {
+            // skip over correctly validating pairs
+            continue;
+        }
+        sum += order[order.len() / 2];
+    }
+    if explain_updates {
+        return print_explanations(&explanations, has_flag(&args, "--json"));
     }
+
     println!("{sum}");
     Ok(())
 }
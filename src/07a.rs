@@ -1,42 +1,42 @@
 // Advent of Code 2024
 // (c) 2024 Mateusz Kwapich
 
-use anyhow::Result;
-#[macro_use]
-extern crate scan_rules;
-
-use scan_rules::scan;
+use anyhow::{Context, Result};
+use num::BigUint;
 use std::io::BufRead;
 
-fn fixable_recuse(tail: &[i64], sum: i64, target_sum: i64) -> bool {
-    if sum > target_sum {
-        return false;
-    }
-    if tail.is_empty() {
-        return sum == target_sum;
-    }
-    let add = fixable_recuse(&tail[1..], sum + tail[0], target_sum);
-    let multiply = fixable_recuse(&tail[1..], sum * tail[0], target_sum);
-    add || multiply
-}
-
-fn fixable(components: &[i64], target_sum: i64) -> bool {
-    fixable_recuse(&components[1..], components[0], target_sum)
-}
+pub mod args;
+pub mod equations;
+use args::*;
+use equations::Solver;
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let ops = arg_value(&args, "--ops").unwrap_or("add,mul");
+    let solver = Solver::with_names(ops)?;
+    let show_witness = has_flag(&args, "--witness");
+
     let stdin = std::io::stdin();
-    let mut sum = 0;
+    let mut sum = BigUint::ZERO;
     for line in stdin.lock().lines() {
         let line = line?;
-        scan!(&line;
-            (let row_sum: i64, ":",  [ let components: i64 ]+) => {
-                if fixable(&components, row_sum) {
-                    sum+=row_sum;
-                }
-            },
-        )
-        .unwrap();
+        // too big for scan! to parse
+        let (row_sum, components) = line
+            .split_once(':')
+            .with_context(|| format!("expected target: operands, got {line:?}"))?;
+        let row_sum: BigUint = row_sum.trim().parse()?;
+        let components = components
+            .split_whitespace()
+            .map(|n| n.parse())
+            .collect::<Result<Vec<BigUint>, _>>()?;
+
+        let solution = solver.solve(row_sum.clone(), &components);
+        if let Some(witness) = solution.witness {
+            if show_witness {
+                println!("{row_sum} = {witness} (1 of {})", solution.count);
+            }
+            sum += row_sum;
+        }
     }
     println!("{sum}");
     Ok(())
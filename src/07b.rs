@@ -1,70 +1,43 @@
 // Advent of Code 2024
 // (c) 2024 Mateusz Kwapich
 
-use anyhow::Result;
-#[macro_use]
-extern crate scan_rules;
-
-use scan_rules::scan;
+use anyhow::{Context, Result};
+use num::BigUint;
 use std::io::BufRead;
 
-fn concat_numbers(a: u64, b: u64) -> u64 {
-    let mut pow = 10;
-    while pow <= b {
-        pow *= 10;
-    }
-    a * pow + b
-}
-
-fn fixable_recuse(tail: &[u64], sum: u64, target_sum: u64) -> bool {
-    if sum > target_sum {
-        return false;
-    }
-    if tail.is_empty() {
-        return sum == target_sum;
-    }
-    // concat
-    if fixable_recuse(&tail[1..], concat_numbers(sum, tail[0]), target_sum) {
-        return true;
-    }
-    // multiply
-    if fixable_recuse(&tail[1..], sum * tail[0], target_sum) {
-        return true;
-    }
-    // add
-    fixable_recuse(&tail[1..], sum + tail[0], target_sum)
-}
-
-fn fixable(components: &[u64], target_sum: u64) -> bool {
-    fixable_recuse(&components[1..], components[0], target_sum)
-}
+pub mod args;
+pub mod equations;
+use args::*;
+use equations::Solver;
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let ops = arg_value(&args, "--ops").unwrap_or("add,mul,concat");
+    let solver = Solver::with_names(ops)?;
+    let show_witness = has_flag(&args, "--witness");
+
     let stdin = std::io::stdin();
-    let mut sum = 0;
+    let mut sum = BigUint::ZERO;
     for line in stdin.lock().lines() {
         let line = line?;
-        scan!(&line;
-            (let row_sum: u64, ":",  [ let components: u64 ]+) => {
-                if fixable(&components, row_sum) {
-                    sum+=row_sum;
-                }
-            },
-        )
-        .unwrap();
+        // too big for scan! to parse
+        let (row_sum, components) = line
+            .split_once(':')
+            .with_context(|| format!("expected target: operands, got {line:?}"))?;
+        let row_sum: BigUint = row_sum.trim().parse()?;
+        let components = components
+            .split_whitespace()
+            .map(|n| n.parse())
+            .collect::<Result<Vec<BigUint>, _>>()?;
+
+        let solution = solver.solve(row_sum.clone(), &components);
+        if let Some(witness) = solution.witness {
+            if show_witness {
+                println!("{row_sum} = {witness} (1 of {})", solution.count);
+            }
+            sum += row_sum;
+        }
     }
     println!("{sum}");
     Ok(())
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_concat() {
-        assert_eq!(concat_numbers(1, 2), 12);
-        assert_eq!(concat_numbers(10, 2), 102);
-        assert_eq!(concat_numbers(2, 10), 210);
-    }
-}
@@ -2,33 +2,40 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-use memoize::memoize;
-use std::io::BufRead;
+use std::{io::BufRead, path::Path};
 
-#[memoize]
-fn num_stones(stone: u64, iter: usize) -> usize {
+pub mod args;
+pub mod memo;
+use args::*;
+use memo::Cache;
+
+fn num_stones(cache: &mut Cache<(u64, usize), usize>, stone: u64, iter: usize) -> usize {
     if iter == 0 {
         return 1;
     }
-
-    if stone == 0 {
-        return num_stones(1, iter - 1);
+    if let Some(res) = cache.get(&(stone, iter)) {
+        return res;
     }
 
     let digits = stone.to_string();
     let len = digits.len();
-    if len % 2 == 0 {
+    let res = if stone == 0 {
+        num_stones(cache, 1, iter - 1)
+    } else if len % 2 == 0 {
         let left = digits[0..len / 2].parse().unwrap();
         let right = digits[len / 2..len].parse().unwrap();
 
-        return num_stones(left, iter - 1) + num_stones(right, iter - 1);
-    }
-
-    num_stones(stone * 2024, iter - 1)
+        num_stones(cache, left, iter - 1) + num_stones(cache, right, iter - 1)
+    } else {
+        num_stones(cache, stone * 2024, iter - 1)
+    };
+    cache.insert((stone, iter), res);
+    res
 }
 
 fn main() -> Result<()> {
     let stdin = std::io::stdin();
+    let args: Vec<String> = std::env::args().collect();
 
     let mut stones = vec![];
     for line in stdin.lock().lines() {
@@ -39,9 +46,25 @@ fn main() -> Result<()> {
         }
     }
 
+    let mut cache = match parse_arg(&args, "--cache-capacity")? {
+        Some(capacity) => Cache::with_capacity(capacity)?,
+        None => Cache::new(),
+    };
+    let cache_file = arg_value(&args, "--cache-file");
+    if let Some(path) = &cache_file
+        && Path::new(path).exists()
+    {
+        cache.load(path)?;
+    }
+
     let mut res = 0;
     for stone in stones {
-        res += num_stones(stone, 75);
+        res += num_stones(&mut cache, stone, 75);
+    }
+
+    eprintln!("cache: {}", cache.stats());
+    if let Some(path) = &cache_file {
+        cache.save(path)?;
     }
 
     println!("{}", res);
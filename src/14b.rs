@@ -6,6 +6,34 @@ use std::{io::BufRead, thread, time::Duration};
 #[macro_use]
 extern crate scan_rules;
 
+pub mod cycle;
+
+type Robot = (i64, i64, i64, i64);
+
+fn advance(robots: &[Robot], w: i64, h: i64, turns: i64) -> Vec<Robot> {
+    robots
+        .iter()
+        .map(|(x, y, vx, vy)| {
+            (
+                (x + turns * (vx + w)) % w,
+                (y + turns * (vy + h)) % h,
+                *vx,
+                *vy,
+            )
+        })
+        .collect()
+}
+
+fn x_variance(robots: &[Robot]) -> f64 {
+    let n = robots.len() as f64;
+    let mean = robots.iter().map(|r| r.0 as f64).sum::<f64>() / n;
+    robots
+        .iter()
+        .map(|r| (r.0 as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n
+}
+
 fn main() -> Result<()> {
     let stdin = std::io::stdin();
 
@@ -18,25 +46,46 @@ fn main() -> Result<()> {
 
         robots.push((x, y, vx, vy));
     }
-    // interesting at 20
-    // interesting at 121
-    // (I'll set to step through those in case those cycle)
-    let start_iter = 121;
-    let step = 101;
-    for (x, y, vx, vy) in robots.iter_mut() {
-        (*x, *y) = (
-            (*x + start_iter * (*vx + w)) % w,
-            (*y + start_iter * (*vy + h)) % h,
-        );
+
+    // The columns repeat much sooner than the whole picture, so once a frame
+    // with interesting columns shows up it's enough to step through the frames
+    // with the same columns. The picture has its robots bunched up in a few
+    // columns, so the interesting frame is the one with the least spread out
+    // x coordinates.
+    let columns: Vec<Robot> = robots
+        .iter()
+        .map(|(x, _y, vx, _vy)| (*x, 0, *vx, 0))
+        .collect();
+    let columns = cycle::brent(columns, |r| advance(r, w, 1, 1));
+    let picture = cycle::brent(robots.clone(), |r| advance(r, w, h, 1));
+    eprintln!(
+        "columns repeat every {} turns, the picture every {}",
+        columns.period, picture.period
+    );
+
+    let mut frame = robots.clone();
+    let mut start_iter = 0;
+    let mut best = f64::INFINITY;
+    for i in 0..columns.period {
+        let variance = x_variance(&frame);
+        if variance < best {
+            (start_iter, best) = (i, variance);
+        }
+        frame = advance(&frame, w, h, 1);
     }
-    for i in (start_iter..).step_by(step as usize) {
+    eprintln!("columns bunch up first at turn {start_iter}");
+
+    let step = columns.period as i64;
+    let mut robots = picture.nth(robots, |r| advance(r, w, h, 1), start_iter);
+    let start_iter = start_iter as i64;
+    for i in (start_iter..start_iter + picture.period as i64).step_by(step as usize) {
         let mut fb = vec![vec![' '; w as usize]; h as usize];
 
         println!("Turn {i}\n\n");
-        for (x, y, vx, vy) in robots.iter_mut() {
+        for (x, y, _vx, _vy) in robots.iter() {
             fb[*y as usize][*x as usize] = '#';
-            (*x, *y) = ((*x + step * (*vx + w)) % w, (*y + step * (*vy + h)) % h);
         }
+        robots = advance(&robots, w, h, step);
 
         for line in fb.iter() {
             for c in line.iter() {
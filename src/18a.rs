@@ -2,50 +2,29 @@
 // (c) 2024 Mateusz Kwapich
 
 use anyhow::Result;
-use std::{
-    collections::{BTreeSet, VecDeque},
-    io::BufRead,
-};
+use std::{collections::VecDeque, io::BufRead};
 #[macro_use]
 extern crate scan_rules;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct Point {
-    x: i64,
-    y: i64,
-}
-
-impl Point {
-    fn new(x: i64, y: i64) -> Self {
-        Self { x, y }
-    }
+pub mod map;
+use map::*;
 
-    pub fn neighbours(&self) -> [Point; 4] {
-        [
-            Self::new(self.x + 1, self.y),
-            Self::new(self.x, self.y + 1),
-            Self::new(self.x, self.y - 1),
-            Self::new(self.x - 1, self.y),
-        ]
-    }
-}
-
-fn shortest_path(map: BTreeSet<Point>, size: i64) -> u64 {
+fn shortest_path(map: SparseMap, size: i64) -> u64 {
     // dbg!(&map, &size);
     let mut queue = VecDeque::new();
     queue.push_back((Point::new(0, 0), 0));
-    let mut seen = BTreeSet::new();
+    let mut seen = PointSet::new(size as usize + 1, size as usize + 1);
     seen.insert(Point::new(0, 0));
 
     while let Some((p, dist)) = queue.pop_front() {
         if p.x == size && p.y == size {
             return dist;
         }
-        for n in p.neighbours() {
-            if p.x < 0 || p.x > size || p.y < 0 || p.y > size {
+        for (n, _dir) in p.neighbours() {
+            if n.x < 0 || n.x > size || n.y < 0 || n.y > size {
                 continue;
             }
-            if map.contains(&n) {
+            if map.get(&n) == Some(b'#') {
                 continue;
             }
             if seen.insert(n) {
@@ -70,7 +49,10 @@ fn main() -> Result<()> {
         incoming_bytes.push(Point::new(x, y));
     }
 
-    let map: BTreeSet<_> = incoming_bytes[0..iter].iter().cloned().collect();
+    let mut map = SparseMap::with_default(b'.');
+    for p in &incoming_bytes[0..iter] {
+        map.set(p, b'#');
+    }
     let path_len = shortest_path(map, size);
     println!("{path_len}");
 
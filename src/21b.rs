@@ -10,7 +10,8 @@ extern crate scan_rules;
 
 pub mod map;
 use map::*;
-use memoize::memoize;
+pub mod memo;
+use memo::Cache;
 
 #[derive(PartialEq, Eq, Clone, Copy, Hash)]
 pub enum PadKind {
@@ -18,7 +19,6 @@ pub enum PadKind {
     DIRPAD,
 }
 
-#[memoize]
 fn shortest_paths(pad_kind: PadKind, start: u8, end: u8) -> Vec<Vec<u8>> {
     let map: &Map = match pad_kind {
         PadKind::NUMPAD => &NUMPAD,
@@ -91,31 +91,46 @@ lazy_static! {
     );
 }
 
-#[memoize]
-fn inception_inner(start: u8, end: u8, pads: Vec<PadKind>) -> usize {
-    let paths = shortest_paths(pads[0], start, end);
-
-    paths
-        .into_iter()
-        .map(|path| inception(path, pads[1..].to_vec()))
-        .min()
-        .unwrap()
+#[derive(Default)]
+struct Solver {
+    paths: Cache<(PadKind, u8, u8), Vec<Vec<u8>>>,
+    presses: Cache<(u8, u8, Vec<PadKind>), usize>,
 }
 
-fn inception(input: Vec<u8>, pads: Vec<PadKind>) -> usize {
-    if pads.is_empty() {
-        return input.len();
+impl Solver {
+    fn inception_inner(&mut self, start: u8, end: u8, pads: &[PadKind]) -> usize {
+        let key = (start, end, pads.to_vec());
+        if let Some(res) = self.presses.get(&key) {
+            return res;
+        }
+        let paths = self.paths.get_or_insert_with((pads[0], start, end), || {
+            shortest_paths(pads[0], start, end)
+        });
+
+        let res = paths
+            .into_iter()
+            .map(|path| self.inception(path, &pads[1..]))
+            .min()
+            .unwrap();
+        self.presses.insert(key, res);
+        res
     }
-    let mut res = Default::default();
-    for (s, e) in Some(b'A')
-        .into_iter()
-        .chain(input.iter().cloned())
-        .tuple_windows()
-    {
-        let best = inception_inner(s, e, pads.clone());
-        res += best;
+
+    fn inception(&mut self, input: Vec<u8>, pads: &[PadKind]) -> usize {
+        if pads.is_empty() {
+            return input.len();
+        }
+        let mut res = Default::default();
+        for (s, e) in Some(b'A')
+            .into_iter()
+            .chain(input.iter().cloned())
+            .tuple_windows()
+        {
+            let best = self.inception_inner(s, e, pads);
+            res += best;
+        }
+        res
     }
-    res
 }
 
 fn main() -> Result<()> {
@@ -126,13 +141,16 @@ fn main() -> Result<()> {
     for _i in 0..25 {
         pads.push(PadKind::DIRPAD);
     }
+    let mut solver = Solver::default();
     for line in stdin.lock().lines() {
         let line = line?;
-        let best = inception(line.as_bytes().to_vec(), pads.clone());
+        let best = solver.inception(line.as_bytes().to_vec(), &pads);
 
         let_scan!(line; (let num: usize, "A"));
         res += num * best;
     }
+    eprintln!("paths cache: {}", solver.paths.stats());
+    eprintln!("presses cache: {}", solver.presses.stats());
     println!("{res}");
     Ok(())
 }
@@ -0,0 +1,60 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use anyhow::{Context, Result};
+use std::str::FromStr;
+
+/// Whether the `--name` switch was passed.
+pub fn has_flag(args: &[String], name: &str) -> bool {
+    args.iter().any(|arg| arg == name)
+}
+
+/// Value following the first `--name`.
+pub fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    arg_values(args, name).next()
+}
+
+/// Values following every `--name`, for options which can be repeated.
+pub fn arg_values<'a>(args: &'a [String], name: &str) -> impl Iterator<Item = &'a str> {
+    args.windows(2)
+        .filter(move |pair| pair[0] == name)
+        .map(|pair| pair[1].as_str())
+}
+
+/// Value following the first `--name`, parsed.
+pub fn parse_arg<T>(args: &[String], name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    arg_value(args, name)
+        .map(|value| {
+            value
+                .parse()
+                .with_context(|| format!("invalid {name} {value:?}"))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        let args = [
+            "prog", "--word", "XMAS", "--list", "--word", "SAMX", "--top", "3",
+        ]
+        .map(String::from);
+        assert!(has_flag(&args, "--list"));
+        assert!(!has_flag(&args, "--json"));
+        assert_eq!(arg_value(&args, "--word"), Some("XMAS"));
+        assert_eq!(
+            arg_values(&args, "--word").collect::<Vec<_>>(),
+            ["XMAS", "SAMX"]
+        );
+        assert_eq!(parse_arg::<usize>(&args, "--top").unwrap(), Some(3));
+        assert_eq!(parse_arg::<usize>(&args, "--dampener").unwrap(), None);
+        assert!(parse_arg::<usize>(&args, "--word").is_err());
+    }
+}
@@ -0,0 +1,654 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use anyhow::Result;
+use std::{
+    fmt,
+    io::{ErrorKind, Read},
+};
+
+/// Longest operand accepted by the instructions.
+const MAX_DIGITS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    pub enabled: bool,
+    pub sum: i64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            enabled: true,
+            sum: 0,
+        }
+    }
+}
+
+pub trait Instruction {
+    fn name(&self) -> &'static str;
+    fn arity(&self) -> usize;
+    fn execute(&self, args: &[i64], state: &mut State);
+}
+
+pub struct Mul;
+
+impl Instruction for Mul {
+    fn name(&self) -> &'static str {
+        "mul"
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn execute(&self, args: &[i64], state: &mut State) {
+        if state.enabled {
+            state.sum += args[0] * args[1];
+        }
+    }
+}
+
+pub struct Do;
+
+impl Instruction for Do {
+    fn name(&self) -> &'static str {
+        "do"
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, _args: &[i64], state: &mut State) {
+        state.enabled = true;
+    }
+}
+
+pub struct Dont;
+
+impl Instruction for Dont {
+    fn name(&self) -> &'static str {
+        "don't"
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, _args: &[i64], state: &mut State) {
+        state.enabled = false;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    OperandTooLong,
+    MissingOperand,
+    WrongArity { expected: usize, got: usize },
+    UnexpectedByte(u8),
+    UnexpectedEnd,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::OperandTooLong => write!(f, "operand longer than {MAX_DIGITS} digits"),
+            Rejection::MissingOperand => write!(f, "missing operand"),
+            Rejection::WrongArity { expected, got } => {
+                write!(f, "expected {expected} operands, got {got}")
+            }
+            Rejection::UnexpectedByte(b) => write!(f, "unexpected byte {:?}", *b as char),
+            Rejection::UnexpectedEnd => write!(f, "unexpected end of input"),
+        }
+    }
+}
+
+/// Registered instruction name followed by an opening parenthesis, and what
+/// came of parsing the rest of it. `start..end` are byte offsets in the input;
+/// for rejected candidates `end` is the offending byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub instruction: usize,
+    pub start: usize,
+    pub end: usize,
+    pub result: Result<Vec<i64>, Rejection>,
+}
+
+#[derive(Default)]
+pub struct Registry {
+    instructions: Vec<Box<dyn Instruction>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `mul`, `do` and `don't`.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(Mul));
+        registry.register(Box::new(Do));
+        registry.register(Box::new(Dont));
+        registry
+    }
+
+    pub fn register(&mut self, instruction: Box<dyn Instruction>) {
+        self.instructions.push(instruction);
+    }
+
+    pub fn instruction(&self, idx: usize) -> &dyn Instruction {
+        self.instructions[idx].as_ref()
+    }
+
+    /// Every candidate instruction in the input, in order.
+    pub fn scan(&self, input: &[u8]) -> Vec<Candidate> {
+        let mut scanner = Scanner::new(self);
+        scanner.feed(input);
+        scanner.finish()
+    }
+
+    /// Executes the instructions while reading the input in chunks, without
+    /// holding all of it in memory.
+    pub fn run_reader(&self, mut reader: impl Read) -> Result<State> {
+        let mut state = State::default();
+        let mut scanner = Scanner::new(self);
+        let mut buf = [0; 64 * 1024];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            scanner.feed(&buf[..n]);
+            self.execute(scanner.drain(), &mut state);
+        }
+        self.execute(scanner.finish(), &mut state);
+        Ok(state)
+    }
+
+    fn execute(&self, candidates: impl IntoIterator<Item = Candidate>, state: &mut State) {
+        for candidate in candidates {
+            if let Ok(args) = &candidate.result {
+                self.instructions[candidate.instruction].execute(args, state);
+            }
+        }
+    }
+
+    /// Executes the accepted candidates in order.
+    pub fn run(&self, candidates: &[Candidate]) -> State {
+        let mut state = State::default();
+        self.execute(candidates.iter().cloned(), &mut state);
+        state
+    }
+
+    pub fn print_candidates(&self, input: &[u8], candidates: &[Candidate]) {
+        for candidate in candidates {
+            let text = String::from_utf8_lossy(&input[candidate.start..candidate.end]);
+            match &candidate.result {
+                Ok(_args) => println!("{}..{} accepted {text}", candidate.start, candidate.end),
+                Err(rejection) => println!(
+                    "{}..{} rejected {text}: {rejection}",
+                    candidate.start, candidate.end
+                ),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ansi,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Enabled,
+    Disabled,
+    /// Instruction which changed the sum.
+    Counted,
+    /// Instruction which didn't do anything as it was disabled.
+    Skipped,
+    SwitchOn,
+    SwitchOff,
+}
+
+impl Style {
+    fn ansi(self) -> &'static str {
+        match self {
+            Style::Enabled => "",
+            Style::Disabled => "\x1b[2m",
+            Style::Counted => "\x1b[1;32m",
+            Style::Skipped => "\x1b[2;33m",
+            Style::SwitchOn => "\x1b[1;36m",
+            Style::SwitchOff => "\x1b[1;35m",
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Style::Enabled => "enabled",
+            Style::Disabled => "disabled",
+            Style::Counted => "counted",
+            Style::Skipped => "skipped",
+            Style::SwitchOn => "switch-on",
+            Style::SwitchOff => "switch-off",
+        }
+    }
+}
+
+const HTML_HEADER: &str = "<style>
+.margin { color: gray; }
+.disabled { opacity: 0.5; }
+.counted { color: green; font-weight: bold; }
+.skipped { color: olive; text-decoration: line-through; }
+.switch-on { color: teal; font-weight: bold; }
+.switch-off { color: purple; font-weight: bold; }
+</style>
+<pre>
+";
+
+impl Registry {
+    /// Style of every byte of the input and the running sum after it.
+    fn styles(&self, input: &[u8], candidates: &[Candidate]) -> Vec<(Style, i64)> {
+        let region = |state: &State| {
+            if state.enabled {
+                Style::Enabled
+            } else {
+                Style::Disabled
+            }
+        };
+        let mut styles = Vec::with_capacity(input.len());
+        let mut state = State::default();
+        for candidate in candidates {
+            styles.resize(candidate.start, (region(&state), state.sum));
+            let before = state;
+            let style = match &candidate.result {
+                Ok(args) => {
+                    let instruction = self.instruction(candidate.instruction);
+                    instruction.execute(args, &mut state);
+                    if state.sum != before.sum {
+                        Style::Counted
+                    } else if state.enabled != before.enabled || instruction.arity() == 0 {
+                        if state.enabled {
+                            Style::SwitchOn
+                        } else {
+                            Style::SwitchOff
+                        }
+                    } else {
+                        Style::Skipped
+                    }
+                }
+                Err(_rejection) => region(&before),
+            };
+            styles.resize(candidate.end, (style, state.sum));
+        }
+        styles.resize(input.len(), (region(&state), state.sum));
+        styles
+    }
+
+    /// Re-prints the input with the instructions and the disabled parts
+    /// highlighted, and the running sum at the end of every line in the
+    /// margin.
+    pub fn annotate(&self, input: &[u8], candidates: &[Candidate], format: Format) -> String {
+        let styles = self.styles(input, candidates);
+        let mut out = String::new();
+        if format == Format::Html {
+            out.push_str(HTML_HEADER);
+        }
+
+        let mut pos = 0;
+        let mut sum = 0;
+        let text = input.strip_suffix(b"\n").unwrap_or(input);
+        for line in text.split(|b| *b == b'\n') {
+            let line_styles = &styles[pos..pos + line.len()];
+            sum = line_styles.last().map_or(sum, |(_style, sum)| *sum);
+            match format {
+                Format::Ansi => out.push_str(&format!("\x1b[2m{sum:>12} |\x1b[0m ")),
+                Format::Html => {
+                    out.push_str(&format!("<span class=\"margin\">{sum:>12} |</span> "))
+                }
+            }
+
+            let mut start = 0;
+            while start < line.len() {
+                let style = line_styles[start].0;
+                let len = line_styles[start..]
+                    .iter()
+                    .take_while(|(s, _sum)| *s == style)
+                    .count();
+                let text = String::from_utf8_lossy(&line[start..start + len]);
+                match format {
+                    Format::Ansi => {
+                        out.push_str(style.ansi());
+                        out.push_str(&text);
+                        out.push_str("\x1b[0m");
+                    }
+                    Format::Html => {
+                        let text = text
+                            .replace('&', "&amp;")
+                            .replace('<', "&lt;")
+                            .replace('>', "&gt;");
+                        out.push_str(&format!("<span class=\"{}\">{text}</span>", style.class()));
+                    }
+                }
+                start += len;
+            }
+            out.push('\n');
+            pos += line.len() + 1;
+        }
+
+        if format == Format::Html {
+            out.push_str("</pre>\n");
+        }
+        out
+    }
+}
+
+/// Operands parsed so far for an instruction whose name was matched.
+struct PendingArgs {
+    instruction: usize,
+    start: usize,
+    args: Vec<i64>,
+    digits: usize,
+}
+
+enum ArgStep {
+    Continue,
+    Accept,
+    Reject(Rejection),
+}
+
+/// Byte at a time automaton finding the candidates, so that the input can be
+/// fed in arbitrary chunks. Every byte is looked at once, except for the one
+/// rejecting a candidate which can start the next one.
+pub struct Scanner<'a> {
+    registry: &'a Registry,
+    /// Absolute offset of the next byte.
+    offset: usize,
+    /// Names matched up to the current byte: (start offset, instruction).
+    partial: Vec<(usize, usize)>,
+    pending: Option<PendingArgs>,
+    candidates: Vec<Candidate>,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(registry: &'a Registry) -> Self {
+        Scanner {
+            registry,
+            offset: 0,
+            partial: vec![],
+            pending: None,
+            candidates: vec![],
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) {
+        for b in chunk {
+            self.feed_byte(*b);
+        }
+    }
+
+    /// Candidates completed so far.
+    pub fn drain(&mut self) -> impl Iterator<Item = Candidate> + '_ {
+        self.candidates.drain(..)
+    }
+
+    /// Ends the input, rejecting the candidate in progress (if any).
+    pub fn finish(mut self) -> Vec<Candidate> {
+        if let Some(pending) = self.pending.take() {
+            self.reject(pending, Rejection::UnexpectedEnd);
+        }
+        self.candidates
+    }
+
+    fn reject(&mut self, pending: PendingArgs, rejection: Rejection) {
+        self.candidates.push(Candidate {
+            instruction: pending.instruction,
+            start: pending.start,
+            end: self.offset,
+            result: Err(rejection),
+        });
+    }
+
+    fn step_args(&self, pending: &mut PendingArgs, b: u8) -> ArgStep {
+        match b {
+            b'0'..=b'9' if pending.digits == MAX_DIGITS => {
+                ArgStep::Reject(Rejection::OperandTooLong)
+            }
+            b'0'..=b'9' => {
+                if pending.digits == 0 {
+                    pending.args.push(0);
+                }
+                let last = pending.args.last_mut().unwrap();
+                *last = *last * 10 + (b - b'0') as i64;
+                pending.digits += 1;
+                ArgStep::Continue
+            }
+            b',' | b')' if pending.digits == 0 && (b == b',' || !pending.args.is_empty()) => {
+                ArgStep::Reject(Rejection::MissingOperand)
+            }
+            b',' => {
+                pending.digits = 0;
+                ArgStep::Continue
+            }
+            b')' => {
+                let arity = self.registry.instructions[pending.instruction].arity();
+                if pending.args.len() == arity {
+                    ArgStep::Accept
+                } else {
+                    ArgStep::Reject(Rejection::WrongArity {
+                        expected: arity,
+                        got: pending.args.len(),
+                    })
+                }
+            }
+            b => ArgStep::Reject(Rejection::UnexpectedByte(b)),
+        }
+    }
+
+    fn feed_byte(&mut self, b: u8) {
+        if let Some(mut pending) = self.pending.take() {
+            match self.step_args(&mut pending, b) {
+                ArgStep::Continue => {
+                    self.pending = Some(pending);
+                    self.offset += 1;
+                    return;
+                }
+                ArgStep::Accept => {
+                    self.candidates.push(Candidate {
+                        instruction: pending.instruction,
+                        start: pending.start,
+                        end: self.offset + 1,
+                        result: Ok(pending.args),
+                    });
+                    self.offset += 1;
+                    return;
+                }
+                // the rejecting byte may start another name
+                ArgStep::Reject(rejection) => self.reject(pending, rejection),
+            }
+        }
+
+        let instructions = &self.registry.instructions;
+        if b == b'(' {
+            // no name contains a parenthesis, so every partial match ends
+            // here and the earliest complete one wins
+            let offset = self.offset;
+            let complete = self
+                .partial
+                .iter()
+                .filter(|(start, ins)| offset - start == instructions[*ins].name().len())
+                .min_by_key(|(start, _ins)| *start);
+            if let Some(&(start, instruction)) = complete {
+                self.pending = Some(PendingArgs {
+                    instruction,
+                    start,
+                    args: vec![],
+                    digits: 0,
+                });
+            }
+            self.partial.clear();
+        } else {
+            let offset = self.offset;
+            self.partial.retain(|(start, ins)| {
+                instructions[*ins].name().as_bytes().get(offset - start) == Some(&b)
+            });
+            for (idx, ins) in instructions.iter().enumerate() {
+                if ins.name().as_bytes().first() == Some(&b) {
+                    self.partial.push((offset, idx));
+                }
+            }
+        }
+        self.offset += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::Lcg;
+
+    #[test]
+    fn test_example() {
+        let input = b"xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+        let registry = Registry::standard();
+        let candidates = registry.scan(input);
+        assert_eq!(registry.run(&candidates).sum, 48);
+        assert_eq!(candidates.len(), 7);
+        assert_eq!(
+            candidates[3],
+            Candidate {
+                instruction: 0,
+                start: 37,
+                end: 46,
+                result: Err(Rejection::UnexpectedByte(b']')),
+            }
+        );
+
+        let mut registry = Registry::new();
+        registry.register(Box::new(Mul));
+        assert_eq!(registry.run(&registry.scan(input)).sum, 161);
+    }
+
+    #[test]
+    fn test_strict_operands() {
+        let registry = Registry::standard();
+        let results: Vec<_> = registry
+            .scan(b"mul(1234,5)mul(,5)mul(1,2,3)mul(1)do(1)mul(4,5")
+            .into_iter()
+            .map(|c| c.result)
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                Err(Rejection::OperandTooLong),
+                Err(Rejection::MissingOperand),
+                Err(Rejection::WrongArity {
+                    expected: 2,
+                    got: 3
+                }),
+                Err(Rejection::WrongArity {
+                    expected: 2,
+                    got: 1
+                }),
+                Err(Rejection::WrongArity {
+                    expected: 0,
+                    got: 1
+                }),
+                Err(Rejection::UnexpectedEnd),
+            ]
+        );
+    }
+
+    struct Nop(&'static str);
+
+    impl Instruction for Nop {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn execute(&self, _args: &[i64], _state: &mut State) {}
+    }
+
+    #[test]
+    fn test_random_chunks() {
+        let mut registry = Registry::standard();
+        // names overlapping with each other and with the standard ones
+        registry.register(Box::new(Nop("xmul")));
+        registry.register(Box::new(Nop("mulmul")));
+        let inputs: [&[u8]; 4] = [
+            b"xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))",
+            b"mulmul(1)mumul(3,4)xmul(7)don't(do()mul(1234,1)mul(12,\nmul(3,4)",
+            b"mul(mul(mul(1,2)do(don't()mul(,)mul(1,2,3)xmulmul(5)mul(999,999",
+            b"",
+        ];
+        let mut rng = Lcg::new(7);
+        for input in inputs {
+            let expected = registry.scan(input);
+            for _i in 0..200 {
+                let mut scanner = Scanner::new(&registry);
+                let mut candidates = vec![];
+                let mut rest = input;
+                while !rest.is_empty() {
+                    let len = (rng.below(5) as usize).min(rest.len());
+                    let (chunk, tail) = rest.split_at(len);
+                    scanner.feed(chunk);
+                    candidates.extend(scanner.drain());
+                    rest = tail;
+                }
+                candidates.extend(scanner.finish());
+                assert_eq!(candidates, expected);
+            }
+            assert_eq!(registry.run_reader(input).unwrap(), registry.run(&expected));
+        }
+    }
+
+    #[test]
+    fn test_overlapping_names() {
+        let mut registry = Registry::standard();
+        registry.register(Box::new(Nop("xmul")));
+        let candidates = registry.scan(b"xmul(2,4)mumul(3,4)");
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|c| (c.instruction, c.start, c.end))
+                .collect::<Vec<_>>(),
+            vec![(3, 0, 8), (0, 11, 19)]
+        );
+        assert_eq!(
+            candidates[0].result,
+            Err(Rejection::WrongArity {
+                expected: 1,
+                got: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_annotate() {
+        let input = b"mul(2,3)don't()\nmul(4,5)do()<mul(1,1)";
+        let registry = Registry::standard();
+        let candidates = registry.scan(input);
+        let html = registry.annotate(input, &candidates, Format::Html);
+        let lines: Vec<_> = html.lines().collect();
+        assert_eq!(
+            lines[9],
+            "<span class=\"margin\">           6 |</span> \
+             <span class=\"counted\">mul(2,3)</span><span class=\"switch-off\">don't()</span>"
+        );
+        assert_eq!(
+            lines[10],
+            "<span class=\"margin\">           7 |</span> \
+             <span class=\"skipped\">mul(4,5)</span><span class=\"switch-on\">do()</span>\
+             <span class=\"enabled\">&lt;</span><span class=\"counted\">mul(1,1)</span>"
+        );
+    }
+}
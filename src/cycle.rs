@@ -0,0 +1,150 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use std::{collections::HashMap, hash::Hash};
+
+/// Shape of the sequence `start, step(start), step(step(start)), ...`: the
+/// states from index `prefix` onwards repeat every `period` steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub prefix: usize,
+    pub period: usize,
+}
+
+impl Cycle {
+    /// Smallest step count that ends in the same state as `n` steps.
+    pub fn reduce(&self, n: usize) -> usize {
+        if n < self.prefix {
+            n
+        } else {
+            self.prefix + (n - self.prefix) % self.period
+        }
+    }
+
+    /// State after `n` steps, simulating at most `prefix + period` of them.
+    pub fn nth<S, F>(&self, start: S, mut step: F, n: usize) -> S
+    where
+        F: FnMut(&S) -> S,
+    {
+        let mut state = start;
+        for _i in 0..self.reduce(n) {
+            state = step(&state);
+        }
+        state
+    }
+}
+
+/// Floyd's tortoise and hare: constant memory, about three passes over the
+/// sequence.
+pub fn floyd<S, F>(start: S, mut step: F) -> Cycle
+where
+    S: Clone + Eq,
+    F: FnMut(&S) -> S,
+{
+    let mut tortoise = step(&start);
+    let mut hare = step(&tortoise);
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        hare = step(&hare);
+    }
+
+    let mut prefix = 0;
+    tortoise = start;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        prefix += 1;
+    }
+
+    let mut period = 1;
+    hare = step(&tortoise);
+    while tortoise != hare {
+        hare = step(&hare);
+        period += 1;
+    }
+    Cycle { prefix, period }
+}
+
+/// Brent's algorithm: constant memory and usually fewer steps than Floyd.
+pub fn brent<S, F>(start: S, mut step: F) -> Cycle
+where
+    S: Clone + Eq,
+    F: FnMut(&S) -> S,
+{
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = step(&start);
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = step(&hare);
+        period += 1;
+    }
+
+    let mut prefix = 0;
+    tortoise = start.clone();
+    hare = start;
+    for _i in 0..period {
+        hare = step(&hare);
+    }
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        prefix += 1;
+    }
+    Cycle { prefix, period }
+}
+
+/// Remembers every state: a single pass, but memory grows with
+/// `prefix + period`.
+pub fn hashed<S, F>(start: S, mut step: F) -> Cycle
+where
+    S: Clone + Eq + Hash,
+    F: FnMut(&S) -> S,
+{
+    let mut seen = HashMap::new();
+    let mut state = start;
+    for i in 0.. {
+        if let Some(first) = seen.insert(state.clone(), i) {
+            return Cycle {
+                prefix: first,
+                period: i - first,
+            };
+        }
+        state = step(&state);
+    }
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(x: &u64) -> u64 {
+        (x * x + 1) % 255
+    }
+
+    #[test]
+    fn test_algorithms_agree() {
+        for start in 0..255 {
+            let expected = hashed(start, step);
+            assert_eq!(floyd(start, step), expected);
+            assert_eq!(brent(start, step), expected);
+        }
+    }
+
+    #[test]
+    fn test_nth() {
+        let cycle = brent(3, step);
+        let mut state = 3;
+        for n in 0..1000 {
+            assert_eq!(cycle.nth(3, step, n), state);
+            state = step(&state);
+        }
+    }
+}
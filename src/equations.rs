@@ -0,0 +1,563 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use anyhow::{Result, bail};
+use num::{BigUint, Integer, ToPrimitive, Zero};
+use std::fmt;
+
+/// Natural numbers the solver works with: `u64` first, and `BigUint` when
+/// something doesn't fit.
+pub trait Natural: Clone + Ord + fmt::Display {
+    fn from_u64(n: u64) -> Self;
+    fn to_u64(&self) -> Option<u64>;
+    fn to_big(&self) -> BigUint;
+    fn is_zero(&self) -> bool;
+    /// `None` when the result doesn't fit.
+    fn plus(&self, b: &Self) -> Option<Self>;
+    /// `None` when the result doesn't fit.
+    fn times(&self, b: &Self) -> Option<Self>;
+    /// `None` when `b` is bigger.
+    fn minus(&self, b: &Self) -> Option<Self>;
+    /// `b` can't be zero.
+    fn div_rem(&self, b: &Self) -> (Self, Self);
+    fn xor(&self, b: &Self) -> Self;
+}
+
+impl Natural for u64 {
+    fn from_u64(n: u64) -> Self {
+        n
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        Some(*self)
+    }
+
+    fn to_big(&self) -> BigUint {
+        BigUint::from(*self)
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+
+    fn plus(&self, b: &Self) -> Option<Self> {
+        self.checked_add(*b)
+    }
+
+    fn times(&self, b: &Self) -> Option<Self> {
+        self.checked_mul(*b)
+    }
+
+    fn minus(&self, b: &Self) -> Option<Self> {
+        self.checked_sub(*b)
+    }
+
+    fn div_rem(&self, b: &Self) -> (Self, Self) {
+        (self / b, self % b)
+    }
+
+    fn xor(&self, b: &Self) -> Self {
+        self ^ b
+    }
+}
+
+impl Natural for BigUint {
+    fn from_u64(n: u64) -> Self {
+        BigUint::from(n)
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        ToPrimitive::to_u64(self)
+    }
+
+    fn to_big(&self) -> BigUint {
+        self.clone()
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+
+    fn plus(&self, b: &Self) -> Option<Self> {
+        Some(self + b)
+    }
+
+    fn times(&self, b: &Self) -> Option<Self> {
+        Some(self * b)
+    }
+
+    fn minus(&self, b: &Self) -> Option<Self> {
+        (self >= b).then(|| self - b)
+    }
+
+    fn div_rem(&self, b: &Self) -> (Self, Self) {
+        Integer::div_rem(self, b)
+    }
+
+    fn xor(&self, b: &Self) -> Self {
+        self ^ b
+    }
+}
+
+/// A result didn't fit, so the search has to be redone with big integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+/// Left operands `a` for which `a op b` gives the wanted result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Preimage<V> {
+    Values(Vec<V>),
+    /// Every value from `lo` to `hi` inclusive, or with no upper bound.
+    Range {
+        lo: V,
+        hi: Option<V>,
+    },
+}
+
+/// Binary operator over natural numbers, evaluated left to right.
+pub trait Operator<V>: Send + Sync {
+    /// `Ok(None)` when the result is undefined.
+    fn apply(&self, a: &V, b: &V) -> Result<Option<V>, Overflow>;
+    /// Undoes `apply` for a known right operand.
+    fn invert(&self, result: &V, b: &V) -> Result<Preimage<V>, Overflow>;
+}
+
+/// Operator which works on both `u64` and `BigUint`, so that the solver can
+/// switch to the latter.
+pub trait AnyOperator: Operator<u64> + Operator<BigUint> {
+    fn name(&self) -> &'static str;
+    fn symbol(&self) -> &'static str;
+}
+
+pub struct Add;
+pub struct Mul;
+pub struct Concat;
+/// Subtraction which can't go below zero.
+pub struct Sub;
+/// Integer division rounding down.
+pub struct Div;
+pub struct Xor;
+
+impl<V: Natural> Operator<V> for Add {
+    fn apply(&self, a: &V, b: &V) -> Result<Option<V>, Overflow> {
+        a.plus(b).map(Some).ok_or(Overflow)
+    }
+
+    fn invert(&self, result: &V, b: &V) -> Result<Preimage<V>, Overflow> {
+        Ok(Preimage::Values(result.minus(b).into_iter().collect()))
+    }
+}
+
+impl AnyOperator for Add {
+    fn name(&self) -> &'static str {
+        "add"
+    }
+
+    fn symbol(&self) -> &'static str {
+        "+"
+    }
+}
+
+impl<V: Natural> Operator<V> for Mul {
+    fn apply(&self, a: &V, b: &V) -> Result<Option<V>, Overflow> {
+        a.times(b).map(Some).ok_or(Overflow)
+    }
+
+    fn invert(&self, result: &V, b: &V) -> Result<Preimage<V>, Overflow> {
+        if b.is_zero() {
+            if result.is_zero() {
+                return Ok(Preimage::Range {
+                    lo: V::from_u64(0),
+                    hi: None,
+                });
+            }
+            return Ok(Preimage::Values(vec![]));
+        }
+        let (a, rem) = result.div_rem(b);
+        Ok(Preimage::Values(
+            rem.is_zero().then_some(a).into_iter().collect(),
+        ))
+    }
+}
+
+impl AnyOperator for Mul {
+    fn name(&self) -> &'static str {
+        "mul"
+    }
+
+    fn symbol(&self) -> &'static str {
+        "*"
+    }
+}
+
+/// Smallest power of ten greater than `b`, so that `a * pow + b` writes the
+/// digits of `b` after the ones of `a`.
+fn concat_pow<V: Natural>(b: &V) -> Result<V, Overflow> {
+    let ten = V::from_u64(10);
+    let mut pow = ten.clone();
+    while pow <= *b {
+        pow = pow.times(&ten).ok_or(Overflow)?;
+    }
+    Ok(pow)
+}
+
+pub fn concat_numbers(a: u64, b: u64) -> Option<u64> {
+    Concat.apply(&a, &b).ok().flatten()
+}
+
+impl<V: Natural> Operator<V> for Concat {
+    fn apply(&self, a: &V, b: &V) -> Result<Option<V>, Overflow> {
+        let a = a.times(&concat_pow(b)?).ok_or(Overflow)?;
+        a.plus(b).map(Some).ok_or(Overflow)
+    }
+
+    fn invert(&self, result: &V, b: &V) -> Result<Preimage<V>, Overflow> {
+        let Some(head) = result.minus(b) else {
+            return Ok(Preimage::Values(vec![]));
+        };
+        let (a, rem) = head.div_rem(&concat_pow(b)?);
+        Ok(Preimage::Values(
+            rem.is_zero().then_some(a).into_iter().collect(),
+        ))
+    }
+}
+
+impl AnyOperator for Concat {
+    fn name(&self) -> &'static str {
+        "concat"
+    }
+
+    fn symbol(&self) -> &'static str {
+        "||"
+    }
+}
+
+impl<V: Natural> Operator<V> for Sub {
+    fn apply(&self, a: &V, b: &V) -> Result<Option<V>, Overflow> {
+        Ok(a.minus(b))
+    }
+
+    fn invert(&self, result: &V, b: &V) -> Result<Preimage<V>, Overflow> {
+        let a = result.plus(b).ok_or(Overflow)?;
+        Ok(Preimage::Values(vec![a]))
+    }
+}
+
+impl AnyOperator for Sub {
+    fn name(&self) -> &'static str {
+        "sub"
+    }
+
+    fn symbol(&self) -> &'static str {
+        "-"
+    }
+}
+
+impl<V: Natural> Operator<V> for Div {
+    fn apply(&self, a: &V, b: &V) -> Result<Option<V>, Overflow> {
+        Ok((!b.is_zero()).then(|| a.div_rem(b).0))
+    }
+
+    fn invert(&self, result: &V, b: &V) -> Result<Preimage<V>, Overflow> {
+        if b.is_zero() {
+            return Ok(Preimage::Values(vec![]));
+        }
+        let lo = result.times(b).ok_or(Overflow)?;
+        // anything past the end doesn't fit either, and would overflow
+        // when evaluated
+        let hi = b.minus(&V::from_u64(1)).and_then(|rem| lo.plus(&rem));
+        Ok(Preimage::Range { lo, hi })
+    }
+}
+
+impl AnyOperator for Div {
+    fn name(&self) -> &'static str {
+        "div"
+    }
+
+    fn symbol(&self) -> &'static str {
+        "/"
+    }
+}
+
+impl<V: Natural> Operator<V> for Xor {
+    fn apply(&self, a: &V, b: &V) -> Result<Option<V>, Overflow> {
+        Ok(Some(a.xor(b)))
+    }
+
+    fn invert(&self, result: &V, b: &V) -> Result<Preimage<V>, Overflow> {
+        Ok(Preimage::Values(vec![result.xor(b)]))
+    }
+}
+
+impl AnyOperator for Xor {
+    fn name(&self) -> &'static str {
+        "xor"
+    }
+
+    fn symbol(&self) -> &'static str {
+        "^"
+    }
+}
+
+pub fn operator(name: &str) -> Option<Box<dyn AnyOperator>> {
+    let op: Box<dyn AnyOperator> = match name {
+        "add" => Box::new(Add),
+        "mul" => Box::new(Mul),
+        "concat" => Box::new(Concat),
+        "sub" => Box::new(Sub),
+        "div" => Box::new(Div),
+        "xor" => Box::new(Xor),
+        _ => return None,
+    };
+    Some(op)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    /// Number of ways to put operators between the operands.
+    pub count: u64,
+    /// The first valid expression found.
+    pub witness: Option<String>,
+}
+
+pub struct Solver {
+    operators: Vec<Box<dyn AnyOperator>>,
+}
+
+impl Solver {
+    pub fn new(operators: Vec<Box<dyn AnyOperator>>) -> Self {
+        Solver { operators }
+    }
+
+    /// Comma separated operator names, like `add,mul,concat`.
+    pub fn with_names(names: &str) -> Result<Self> {
+        let mut operators = vec![];
+        for name in names.split(',') {
+            let Some(op) = operator(name.trim()) else {
+                bail!("unknown operator {name:?}");
+            };
+            operators.push(op);
+        }
+        Ok(Solver::new(operators))
+    }
+
+    /// Counts the ways to put operators between `operands` to get `target`,
+    /// working backwards from the target: the last operator has to be
+    /// undone to get the value the other operands should make.
+    ///
+    /// Runs on `u64` when the numbers fit, and starts over with `BigUint`
+    /// if anything along the way doesn't.
+    pub fn solve<V: Natural>(&self, target: V, operands: &[V]) -> Solution {
+        let small: Option<Vec<u64>> = operands.iter().map(|n| n.to_u64()).collect();
+        if let (Some(target), Some(operands)) = (target.to_u64(), small)
+            && let Ok(solution) = self.search(&target, &operands)
+        {
+            return solution;
+        }
+        let operands: Vec<BigUint> = operands.iter().map(|n| n.to_big()).collect();
+        self.search(&target.to_big(), &operands)
+            .expect("big integers don't overflow")
+    }
+
+    fn search<V: Natural>(&self, target: &V, operands: &[V]) -> Result<Solution, Overflow>
+    where
+        dyn AnyOperator: Operator<V>,
+    {
+        let mut search = Search {
+            operators: &self.operators,
+            count: 0,
+            witness: None,
+        };
+        if !operands.is_empty() {
+            search.back(operands, target, &mut vec![])?;
+        }
+        Ok(Solution {
+            count: search.count,
+            witness: search.witness.map(|ops| self.expression(operands, &ops)),
+        })
+    }
+
+    fn expression<V: Natural>(&self, operands: &[V], ops: &[usize]) -> String {
+        let mut expr = operands[0].to_string();
+        for (op, b) in ops.iter().zip(operands[1..].iter()) {
+            expr += &format!(" {} {b}", self.operators[*op].symbol());
+        }
+        expr
+    }
+}
+
+struct Search<'a> {
+    operators: &'a [Box<dyn AnyOperator>],
+    count: u64,
+    witness: Option<Vec<usize>>,
+}
+
+impl Search<'_> {
+    /// `suffix` holds the operators already undone, last one first.
+    fn back<V: Natural>(
+        &mut self,
+        operands: &[V],
+        target: &V,
+        suffix: &mut Vec<usize>,
+    ) -> Result<(), Overflow>
+    where
+        dyn AnyOperator: Operator<V>,
+    {
+        let (b, rest) = operands.split_last().unwrap();
+        if rest.is_empty() {
+            if b == target {
+                self.count += 1;
+                self.witness
+                    .get_or_insert_with(|| suffix.iter().rev().copied().collect());
+            }
+            return Ok(());
+        }
+        for (i, op) in self.operators.iter().enumerate() {
+            suffix.push(i);
+            match op.invert(target, b)? {
+                Preimage::Values(values) => {
+                    for a in values {
+                        self.back(rest, &a, suffix)?;
+                    }
+                }
+                // too many targets to go on backwards, try every way to
+                // evaluate the rest instead
+                Preimage::Range { lo, hi } => {
+                    let range = (lo, hi);
+                    self.forward(rest, 1, rest[0].clone(), &range, &mut vec![], suffix)?;
+                }
+            }
+            suffix.pop();
+        }
+        Ok(())
+    }
+
+    fn forward<V: Natural>(
+        &mut self,
+        operands: &[V],
+        idx: usize,
+        value: V,
+        range: &(V, Option<V>),
+        prefix: &mut Vec<usize>,
+        suffix: &[usize],
+    ) -> Result<(), Overflow>
+    where
+        dyn AnyOperator: Operator<V>,
+    {
+        if idx == operands.len() {
+            let (lo, hi) = range;
+            if value >= *lo && hi.as_ref().is_none_or(|hi| value <= *hi) {
+                self.count += 1;
+                self.witness.get_or_insert_with(|| {
+                    prefix.iter().chain(suffix.iter().rev()).copied().collect()
+                });
+            }
+            return Ok(());
+        }
+        for (i, op) in self.operators.iter().enumerate() {
+            if let Some(value) = op.apply(&value, &operands[idx])? {
+                prefix.push(i);
+                self.forward(operands, idx + 1, value, range, prefix, suffix)?;
+                prefix.pop();
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [(u64, &[u64]); 9] = [
+        (190, &[10, 19]),
+        (3267, &[81, 40, 27]),
+        (83, &[17, 5]),
+        (156, &[15, 6]),
+        (7290, &[6, 8, 6, 15]),
+        (161011, &[16, 10, 13]),
+        (192, &[17, 8, 14]),
+        (21037, &[9, 7, 18, 13]),
+        (292, &[11, 6, 16, 20]),
+    ];
+
+    fn total(solver: &Solver) -> u64 {
+        EXAMPLE
+            .iter()
+            .filter(|(target, operands)| solver.solve(*target, operands).count > 0)
+            .map(|(target, _operands)| target)
+            .sum()
+    }
+
+    #[test]
+    fn test_concat() {
+        assert_eq!(concat_numbers(1, 2), Some(12));
+        assert_eq!(concat_numbers(10, 2), Some(102));
+        assert_eq!(concat_numbers(2, 10), Some(210));
+    }
+
+    #[test]
+    fn test_example() {
+        assert_eq!(total(&Solver::with_names("add,mul").unwrap()), 3749);
+        let solver = Solver::with_names("add,mul,concat").unwrap();
+        assert_eq!(total(&solver), 11387);
+        assert_eq!(
+            solver.solve(3267, &[81, 40, 27]),
+            Solution {
+                count: 2,
+                witness: Some("81 * 40 + 27".to_string())
+            }
+        );
+        assert_eq!(
+            solver.solve(7290, &[6, 8, 6, 15]).witness,
+            Some("6 * 8 || 6 * 15".to_string())
+        );
+    }
+
+    #[test]
+    fn test_other_operators() {
+        let solver = Solver::with_names("sub,div,xor,mul").unwrap();
+        assert_eq!(
+            solver.solve(5, &[21, 4]).witness,
+            Some("21 / 4".to_string())
+        );
+        assert_eq!(solver.solve(5, &[6, 3]).witness, Some("6 ^ 3".to_string()));
+        assert_eq!(solver.solve(3, &[7, 4]).witness, Some("7 - 4".to_string()));
+        // anything times zero
+        assert_eq!(solver.solve(0, &[2, 0]).count, 1);
+        assert_eq!(solver.solve(0, &[9, 2, 0]).count, 4);
+        assert!(Solver::with_names("add,pow").is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let solver = Solver::with_names("add,mul,concat,div").unwrap();
+        let max = u64::MAX;
+        // the target doesn't fit
+        let target = BigUint::from(max) * 2_u32;
+        let operands = [max, 2].map(BigUint::from);
+        assert_eq!(
+            solver.solve(target, &operands).witness,
+            Some(format!("{max} * 2"))
+        );
+        assert_eq!(
+            solver.solve(
+                BigUint::from(2_u32).pow(64),
+                &[1844674407370955161_u64, 6].map(BigUint::from)
+            ),
+            Solution {
+                count: 1,
+                witness: Some("1844674407370955161 || 6".to_string())
+            }
+        );
+        // only the intermediate result doesn't fit
+        assert_eq!(
+            solver.solve(max / 2, &[max, 2, 4]),
+            Solution {
+                count: 1,
+                witness: Some(format!("{max} * 2 / 4"))
+            }
+        );
+    }
+}
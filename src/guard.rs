@@ -0,0 +1,597 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use crate::args::{has_flag, parse_arg};
+use crate::map::{Dir, Map, Point, PointSet, StateIndex, StateSet};
+use anyhow::Result;
+use rayon::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
+use strum::{Display, EnumString};
+
+pub fn start(map: &Map) -> Option<Point> {
+    map.all_points().find(|(_p, v)| *v == b'^').map(|(p, _v)| p)
+}
+
+/// What the guard starting from `^` did until it left the map or started
+/// going in circles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatrolReport {
+    /// Distinct tiles the guard stood on, in the order it first got there.
+    pub visited: Vec<Point>,
+    /// Every position and direction of the guard, turns included.
+    pub path: Vec<(Point, Dir)>,
+    pub end: Outcome,
+    pub turns: usize,
+}
+
+impl PatrolReport {
+    /// Copy of the map with the visited tiles marked with `X`.
+    pub fn render(&self, map: &Map) -> Map {
+        let mut map = map.clone();
+        for p in self.visited.iter() {
+            map.set(p, b'X');
+        }
+        map
+    }
+}
+
+pub fn walk(map: &Map) -> Option<PatrolReport> {
+    let mut pos = start(map)?;
+    let mut dir = Dir::Up;
+
+    let mut seen = PointSet::for_map(map);
+    let mut vis = StateSet::for_map(map);
+    let mut visited = vec![];
+    let mut path = vec![];
+    let mut turns = 0;
+    let end = loop {
+        if !vis.insert((pos, dir)) {
+            break Outcome::Looped(pos, dir);
+        }
+        if seen.insert(pos) {
+            visited.push(pos);
+        }
+        path.push((pos, dir));
+        let new_pos = pos.step(dir);
+        match map.get(&new_pos) {
+            None => break Outcome::Exited(pos),
+            Some(b'#') => {
+                dir = dir.clockwise_rotate();
+                turns += 1;
+            }
+            Some(_) => pos = new_pos,
+        }
+    };
+    Some(PatrolReport {
+        visited,
+        path,
+        end,
+        turns,
+    })
+}
+
+/// Whether the guard starting from `^` ends up going in circles.
+pub fn loops(map: &Map) -> bool {
+    walk(map).is_some_and(|report| matches!(report.end, Outcome::Looped(..)))
+}
+
+/// For every tile and direction, the tile where the guard stops in front of
+/// the next obstacle, or `None` if it walks off the map.
+#[derive(Debug, Clone)]
+pub struct JumpTable {
+    w: usize,
+    stops: Vec<Option<Point>>,
+}
+
+impl JumpTable {
+    pub fn new(map: &Map) -> Self {
+        let w = map.width();
+        let mut table = JumpTable {
+            w,
+            stops: vec![None; w * map.height() * Dir::COUNT],
+        };
+        for dir in [Dir::Up, Dir::Right, Dir::Down, Dir::Left] {
+            // fill in the tiles closest to the edge the guard is heading to
+            // first, so that the next tile's stop is always known
+            let mut points: Vec<Point> = map.all_points().map(|(p, _v)| p).collect();
+            points.sort_by_key(|p| match dir {
+                Dir::Up => p.y,
+                Dir::Down => -p.y,
+                Dir::Left => p.x,
+                Dir::Right => -p.x,
+            });
+            for p in points {
+                let next = p.step(dir);
+                let stop = match map.get(&next) {
+                    None => None,
+                    Some(b'#') => Some(p),
+                    Some(_) => table.stop(next, dir),
+                };
+                let idx = table.index(p, dir);
+                table.stops[idx] = stop;
+            }
+        }
+        table
+    }
+
+    fn index(&self, p: Point, dir: Dir) -> usize {
+        (p.y as usize * self.w + p.x as usize) * Dir::COUNT + dir.index()
+    }
+
+    pub fn stop(&self, p: Point, dir: Dir) -> Option<Point> {
+        self.stops[self.index(p, dir)]
+    }
+
+    /// Same as `stop`, but with an extra obstacle on the map.
+    pub fn stop_with(&self, p: Point, dir: Dir, obstacle: Point) -> Option<Point> {
+        let stop = self.stop(p, dir);
+        let step = p.step(dir);
+        let (dx, dy) = (step.x - p.x, step.y - p.y);
+        let (ox, oy) = (obstacle.x - p.x, obstacle.y - p.y);
+        // distance to the obstacle if it's straight ahead
+        let dist = match (dx, dy) {
+            (0, _) if ox == 0 => oy * dy,
+            (_, 0) if oy == 0 => ox * dx,
+            _ => return stop,
+        };
+        if dist <= 0 {
+            return stop;
+        }
+        let blocks = match stop {
+            None => true,
+            Some(s) => dist <= (s.x - p.x) * dx + (s.y - p.y) * dy,
+        };
+        if blocks {
+            Some(Point::new(p.x + dx * (dist - 1), p.y + dy * (dist - 1)))
+        } else {
+            stop
+        }
+    }
+}
+
+/// Whether the guard at `pos` facing `dir` goes in circles once `obstacle`
+/// is added. Moves from one obstacle to the next, so only the turns need to
+/// be remembered.
+fn loops_from(jumps: &JumpTable, obstacle: Point, mut pos: Point, mut dir: Dir) -> bool {
+    let mut turns = HashSet::new();
+    while let Some(stop) = jumps.stop_with(pos, dir, obstacle) {
+        pos = stop;
+        dir = dir.clockwise_rotate();
+        if !turns.insert((pos, dir)) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Every empty tile where a new obstacle makes the guard go in circles.
+///
+/// Obstacles off the guard's path don't change it, so only the tiles on the
+/// path are tried, each starting from where the guard was just before first
+/// reaching the tile.
+pub fn loop_obstacles(map: &Map) -> Vec<Point> {
+    let Some(report) = walk(map) else {
+        return vec![];
+    };
+    let jumps = JumpTable::new(map);
+
+    let mut seen = PointSet::for_map(map);
+    seen.insert(report.path[0].0);
+    let mut candidates = vec![];
+    for w in report.path.windows(2) {
+        let ((pos, dir), (next, _next_dir)) = (w[0], w[1]);
+        if next != pos && seen.insert(next) {
+            candidates.push((next, pos, dir));
+        }
+    }
+
+    let mut obstacles: Vec<Point> = candidates
+        .into_par_iter()
+        .filter(|(obstacle, pos, dir)| loops_from(&jumps, *obstacle, *pos, *dir))
+        .map(|(obstacle, _pos, _dir)| obstacle)
+        .collect();
+    if let Outcome::Looped(..) = report.end {
+        // the guard goes in circles anyway
+        obstacles.extend(
+            map.all_points()
+                .filter(|(p, v)| *v == b'.' && !seen.contains(p))
+                .map(|(p, _v)| p),
+        );
+    }
+    obstacles
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Display, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum TurnPolicy {
+    #[default]
+    Right,
+    Left,
+    /// Right first, then left, then right again and so on.
+    Alternate,
+    UTurn,
+}
+
+impl TurnPolicy {
+    /// Where a guard facing `dir` turns after already turning `turns` times.
+    fn turn(self, dir: Dir, turns: usize) -> Dir {
+        match self {
+            TurnPolicy::Right => dir.clockwise_rotate(),
+            TurnPolicy::Left => dir.counter_clockwise_rotate(),
+            TurnPolicy::Alternate if turns.is_multiple_of(2) => dir.clockwise_rotate(),
+            TurnPolicy::Alternate => dir.counter_clockwise_rotate(),
+            TurnPolicy::UTurn => dir.clockwise_rotate().clockwise_rotate(),
+        }
+    }
+}
+
+/// How guards move in `simulate`: `#` tiles are obstacles, guards start on
+/// `^`, `>`, `v` and `<` facing that way, and a guard stepping on a
+/// lowercase letter other than `v` is teleported to the other tile with the
+/// same letter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PatrolRules {
+    pub turn: TurnPolicy,
+    /// Guards walking off an edge come back from the opposite one.
+    pub wrap: bool,
+}
+
+impl PatrolRules {
+    /// Overrides the rules with `--turn` and `--wrap` flags.
+    pub fn with_args(self, args: &[String]) -> Result<Self> {
+        let mut rules = self;
+        if let Some(turn) = parse_arg(args, "--turn")? {
+            rules.turn = turn;
+        }
+        rules.wrap |= has_flag(args, "--wrap");
+        Ok(rules)
+    }
+}
+
+/// Guard's direction, and with alternating turns which way it turns next.
+#[derive(Debug, Clone, Copy)]
+struct Facing {
+    dir: Dir,
+    turns_left: bool,
+}
+
+impl StateIndex for Facing {
+    const COUNT: usize = Dir::COUNT * 2;
+
+    fn index(self) -> usize {
+        self.dir.index() * 2 + self.turns_left as usize
+    }
+
+    fn from_index(idx: usize) -> Self {
+        Facing {
+            dir: Dir::from_index(idx / 2),
+            turns_left: idx % 2 == 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Walked off the map from this tile.
+    Exited(Point),
+    /// Came back to this tile facing the same way, so it'll go in circles.
+    Looped(Point, Dir),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Exited(p) => write!(f, "left the map at {},{}", p.x, p.y),
+            Outcome::Looped(p, dir) => write!(f, "looped at {},{} facing {dir:?}", p.x, p.y),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuardReport {
+    pub start: Point,
+    pub dir: Dir,
+    /// Distinct tiles the guard stood on, in the order it first got there.
+    pub visited: Vec<Point>,
+    pub outcome: Outcome,
+}
+
+/// Guards ending up on the same tile, or swapping places, during a tick.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collision {
+    pub tick: usize,
+    pub point: Point,
+    pub guards: Vec<usize>,
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tick {}: guards {} collided at {},{}",
+            self.tick,
+            self.guards
+                .iter()
+                .map(|g| g.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.point.x,
+            self.point.y
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    pub guards: Vec<GuardReport>,
+    pub collisions: Vec<Collision>,
+}
+
+struct Guard {
+    pos: Point,
+    dir: Dir,
+    turns: usize,
+    seen: PointSet,
+    visited: Vec<Point>,
+    states: StateSet<Facing>,
+    outcome: Option<Outcome>,
+}
+
+/// Direction of the guard drawn as `tile`, if it is one.
+fn guard_dir(tile: u8) -> Option<Dir> {
+    match tile {
+        b'^' => Some(Dir::Up),
+        b'>' => Some(Dir::Right),
+        b'v' => Some(Dir::Down),
+        b'<' => Some(Dir::Left),
+        _ => None,
+    }
+}
+
+/// Moves all the guards one step (or turn) per tick until each of them has
+/// left the map or started going in circles. Guards don't block each other,
+/// and a guard which is done no longer takes part in collisions.
+pub fn simulate(map: &Map, rules: PatrolRules) -> Simulation {
+    let mut letters: BTreeMap<u8, Vec<Point>> = BTreeMap::new();
+    for (p, v) in map.all_points() {
+        if v.is_ascii_lowercase() && guard_dir(v).is_none() {
+            letters.entry(v).or_default().push(p);
+        }
+    }
+    let mut teleports = HashMap::new();
+    for points in letters.values() {
+        if let [a, b] = points[..] {
+            teleports.insert(a, b);
+            teleports.insert(b, a);
+        }
+    }
+
+    let mut guards: Vec<Guard> = map
+        .all_points()
+        .filter_map(|(pos, v)| {
+            Some(Guard {
+                pos,
+                dir: guard_dir(v)?,
+                turns: 0,
+                seen: PointSet::for_map(map),
+                visited: vec![],
+                states: StateSet::for_map(map),
+                outcome: None,
+            })
+        })
+        .collect();
+    let starts: Vec<(Point, Dir)> = guards.iter().map(|g| (g.pos, g.dir)).collect();
+
+    let mut collisions = vec![];
+    let mut tick = 0;
+    while guards.iter().any(|g| g.outcome.is_none()) {
+        tick += 1;
+        let before: Vec<Point> = guards.iter().map(|g| g.pos).collect();
+        for guard in guards.iter_mut().filter(|g| g.outcome.is_none()) {
+            let facing = Facing {
+                dir: guard.dir,
+                turns_left: rules.turn == TurnPolicy::Alternate && !guard.turns.is_multiple_of(2),
+            };
+            if !guard.states.insert((guard.pos, facing)) {
+                guard.outcome = Some(Outcome::Looped(guard.pos, guard.dir));
+                continue;
+            }
+            if guard.seen.insert(guard.pos) {
+                guard.visited.push(guard.pos);
+            }
+            let mut next = guard.pos.step(guard.dir);
+            if rules.wrap {
+                let (w, h) = (map.width() as i64, map.height() as i64);
+                next = Point::new(next.x.rem_euclid(w), next.y.rem_euclid(h));
+            }
+            match map.get(&next) {
+                None => guard.outcome = Some(Outcome::Exited(guard.pos)),
+                Some(b'#') => {
+                    guard.dir = rules.turn.turn(guard.dir, guard.turns);
+                    guard.turns += 1;
+                }
+                Some(_) => guard.pos = *teleports.get(&next).unwrap_or(&next),
+            }
+        }
+
+        let active: Vec<usize> = (0..guards.len())
+            .filter(|i| guards[*i].outcome.is_none())
+            .collect();
+        let mut by_point: BTreeMap<Point, Vec<usize>> = BTreeMap::new();
+        for i in active.iter() {
+            by_point.entry(guards[*i].pos).or_default().push(*i);
+        }
+        for (point, together) in by_point {
+            // guards which were already together and stayed put don't count
+            if together.len() > 1 && together.iter().any(|i| before[*i] != point) {
+                collisions.push(Collision {
+                    tick,
+                    point,
+                    guards: together,
+                });
+            }
+        }
+        for (n, i) in active.iter().enumerate() {
+            for j in active[n + 1..].iter() {
+                let (a, b) = (&guards[*i], &guards[*j]);
+                if a.pos != b.pos && a.pos == before[*j] && b.pos == before[*i] {
+                    collisions.push(Collision {
+                        tick,
+                        point: a.pos,
+                        guards: vec![*i, *j],
+                    });
+                }
+            }
+        }
+    }
+
+    Simulation {
+        guards: guards
+            .into_iter()
+            .zip(starts)
+            .map(|(guard, (start, dir))| GuardReport {
+                start,
+                dir,
+                visited: guard.visited,
+                outcome: guard.outcome.unwrap(),
+            })
+            .collect(),
+        collisions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wide_map() {
+        let wide = Map::parse(".#........\n.^........");
+        let report = walk(&wide).unwrap();
+        assert_eq!(report.visited.len(), 9);
+        assert_eq!(report.path.len(), 10);
+        assert_eq!(report.turns, 1);
+        assert_eq!(report.end, Outcome::Exited(Point::new(9, 1)));
+        assert!(!loops(&wide));
+
+        let wide = Map::parse(".#......\n...#....\n#^......\n..#.....");
+        assert!(loops(&wide));
+    }
+
+    #[test]
+    fn test_tall_map() {
+        let tall = Map::parse("..\n..\n..\n..\n..\n..\n..\n..\n..\n^.");
+        assert_eq!(walk(&tall).unwrap().visited.len(), 10);
+
+        let tall = Map::parse(".#..\n...#\n#^..\n..#.\n....\n....");
+        assert!(loops(&tall));
+    }
+
+    #[test]
+    fn test_loop_obstacles() {
+        let example = Map::parse(
+            "....#.....\n.........#\n..........\n..#.......\n.......#..\n\
+             ..........\n.#..^.....\n........#.\n#.........\n......#...",
+        );
+        let mut obstacles = loop_obstacles(&example);
+        obstacles.sort();
+        let mut expected = vec![];
+        for (p, tile) in example.all_points() {
+            if tile != b'.' {
+                continue;
+            }
+            let mut new_map = example.clone();
+            new_map.set(&p, b'#');
+            if loops(&new_map) {
+                expected.push(p);
+            }
+        }
+        expected.sort();
+        assert_eq!(obstacles.len(), 6);
+        assert_eq!(obstacles, expected);
+    }
+
+    #[test]
+    fn test_simulate() {
+        let example = Map::parse(
+            "....#.....\n.........#\n..........\n..#.......\n.......#..\n\
+             ..........\n.#..^.....\n........#.\n#.........\n......#...",
+        );
+        let simulation = simulate(&example, PatrolRules::default());
+        assert_eq!(simulation.guards.len(), 1);
+        assert_eq!(simulation.guards[0].visited.len(), 41);
+        assert_eq!(
+            simulation.guards[0].outcome,
+            Outcome::Exited(Point::new(7, 9))
+        );
+
+        let simulation = simulate(&Map::parse(">...<\n.><.."), PatrolRules::default());
+        assert_eq!(
+            simulation.collisions,
+            vec![
+                Collision {
+                    tick: 1,
+                    point: Point::new(2, 1),
+                    guards: vec![2, 3]
+                },
+                Collision {
+                    tick: 2,
+                    point: Point::new(2, 0),
+                    guards: vec![0, 1]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_patrol_rules() {
+        let u_turn = PatrolRules {
+            turn: TurnPolicy::UTurn,
+            wrap: false,
+        };
+        let simulation = simulate(&Map::parse(".#.\n...\n.^.\n.#."), u_turn);
+        assert_eq!(simulation.guards[0].visited.len(), 2);
+        assert!(matches!(simulation.guards[0].outcome, Outcome::Looped(..)));
+
+        let wrap = PatrolRules {
+            turn: TurnPolicy::Right,
+            wrap: true,
+        };
+        let simulation = simulate(&Map::parse("..\n^."), wrap);
+        assert_eq!(simulation.guards[0].visited.len(), 2);
+        assert!(matches!(simulation.guards[0].outcome, Outcome::Looped(..)));
+
+        let simulation = simulate(&Map::parse("a.#\n...\n^.a"), PatrolRules::default());
+        assert_eq!(simulation.guards[0].visited.len(), 4);
+        assert_eq!(
+            simulation.guards[0].outcome,
+            Outcome::Exited(Point::new(2, 1))
+        );
+
+        // `v` is a guard, not a teleport
+        let simulation = simulate(
+            &Map::parse(">.v..\n.....\n.....\n..v.."),
+            PatrolRules::default(),
+        );
+        assert_eq!(
+            simulation.guards[0].visited,
+            (0..5).map(|x| Point::new(x, 0)).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            simulation.guards[1].outcome,
+            Outcome::Exited(Point::new(2, 3))
+        );
+
+        let args = ["--turn", "alternate", "--wrap"].map(String::from);
+        assert_eq!(
+            PatrolRules::default().with_args(&args).unwrap(),
+            PatrolRules {
+                turn: TurnPolicy::Alternate,
+                wrap: true
+            }
+        );
+    }
+}
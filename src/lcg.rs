@@ -0,0 +1,21 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+/// Linear congruential generator for the randomized tests: deterministic, so
+/// a failing case reproduces, and small enough not to need a crate.
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Lcg(seed)
+    }
+
+    /// Next number in `0..n`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % n
+    }
+}
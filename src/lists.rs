@@ -0,0 +1,583 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use strum::{Display, EnumString};
+
+/// 80MB of numbers per column.
+pub const DEFAULT_RUN_SIZE: usize = 10_000_000;
+
+static RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts more numbers than fit in memory: every `run_size` numbers are sorted
+/// and spilled to a temporary file, and the files are merged when reading.
+pub struct ExternalSorter {
+    run_size: usize,
+    buf: Vec<i64>,
+    runs: Vec<PathBuf>,
+}
+
+impl ExternalSorter {
+    pub fn new(run_size: usize) -> Self {
+        ExternalSorter {
+            run_size,
+            buf: Vec::with_capacity(run_size),
+            runs: vec![],
+        }
+    }
+
+    pub fn push(&mut self, n: i64) -> Result<()> {
+        self.buf.push(n);
+        if self.buf.len() >= self.run_size {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        self.buf.sort_unstable();
+        let path = std::env::temp_dir().join(format!(
+            "aoc2024_run_{}_{}",
+            std::process::id(),
+            RUN_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut writer = BufWriter::new(
+            File::create(&path).with_context(|| format!("creating {}", path.display()))?,
+        );
+        for n in self.buf.drain(..) {
+            writer.write_all(&n.to_le_bytes())?;
+        }
+        writer.flush()?;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<SortedNumbers> {
+        if !self.buf.is_empty() && !self.runs.is_empty() {
+            self.spill()?;
+        }
+        self.buf.sort_unstable();
+        let mut readers = vec![];
+        let mut heap = BinaryHeap::new();
+        for path in self.runs.iter() {
+            let mut reader = BufReader::new(File::open(path)?);
+            if let Some(n) = read_number(&mut reader)? {
+                heap.push(Reverse((n, readers.len())));
+            }
+            readers.push(reader);
+        }
+        Ok(SortedNumbers {
+            memory: std::mem::take(&mut self.buf).into_iter(),
+            readers,
+            heap,
+            runs: std::mem::take(&mut self.runs),
+        })
+    }
+}
+
+impl Drop for ExternalSorter {
+    fn drop(&mut self) {
+        for path in self.runs.iter() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn read_number(reader: &mut impl Read) -> Result<Option<i64>> {
+    let mut bytes = [0; 8];
+    match reader.read_exact(&mut bytes) {
+        Ok(()) => Ok(Some(i64::from_le_bytes(bytes))),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Output of `ExternalSorter`: either the numbers that never left memory or a
+/// k-way merge of the spilled runs.
+pub struct SortedNumbers {
+    memory: std::vec::IntoIter<i64>,
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<(i64, usize)>>,
+    runs: Vec<PathBuf>,
+}
+
+impl Iterator for SortedNumbers {
+    type Item = Result<i64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.readers.is_empty() {
+            return self.memory.next().map(Ok);
+        }
+        let Reverse((n, run)) = self.heap.pop()?;
+        match read_number(&mut self.readers[run]) {
+            Ok(Some(next)) => self.heap.push(Reverse((next, run))),
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        Some(Ok(n))
+    }
+}
+
+impl Drop for SortedNumbers {
+    fn drop(&mut self) {
+        for path in self.runs.iter() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    /// Sum of distances between the n-th smallest numbers of both lists.
+    pub distance: i64,
+    /// Sum of every left number times its number of occurences on the right.
+    pub similarity: i64,
+}
+
+/// Collapses a sorted stream into `(value, count)` groups.
+#[derive(Default)]
+struct Groups {
+    current: Option<(i64, i64)>,
+}
+
+impl Groups {
+    fn push(&mut self, n: i64) -> Option<(i64, i64)> {
+        match self.current {
+            Some((v, ref mut count)) if v == n => {
+                *count += 1;
+                None
+            }
+            _ => self.current.replace((n, 1)),
+        }
+    }
+}
+
+/// Matches up the groups of equal numbers of two sorted lists read in
+/// lockstep. A finished group waits in a queue only until the other list
+/// gets past its value, so a long run on one side doesn't hold up the groups
+/// of the other.
+#[derive(Default)]
+struct GroupMatcher {
+    groups_a: Groups,
+    groups_b: Groups,
+    pending_a: VecDeque<(i64, i64)>,
+    pending_b: VecDeque<(i64, i64)>,
+    similarity: i64,
+}
+
+impl GroupMatcher {
+    fn push(&mut self, a: i64, b: i64) {
+        self.pending_a.extend(self.groups_a.push(a));
+        self.pending_b.extend(self.groups_b.push(b));
+        self.match_pending();
+    }
+
+    fn finish(mut self) -> i64 {
+        self.pending_a.extend(self.groups_a.current.take());
+        self.pending_b.extend(self.groups_b.current.take());
+        self.match_pending();
+        self.similarity
+    }
+
+    fn match_pending(&mut self) {
+        let (pending_a, pending_b) = (&mut self.pending_a, &mut self.pending_b);
+        while let (Some((va, ca)), Some((vb, cb))) = (pending_a.front(), pending_b.front()) {
+            if va == vb {
+                self.similarity += va * ca * cb;
+                pending_a.pop_front();
+                pending_b.pop_front();
+            } else if va < vb {
+                pending_a.pop_front();
+            } else {
+                pending_b.pop_front();
+            }
+        }
+        drop_passed(pending_a, &self.groups_b);
+        drop_passed(pending_b, &self.groups_a);
+    }
+}
+
+/// Drops the groups which the other list, now in its `other.current` group,
+/// has already got past without a match.
+fn drop_passed(pending: &mut VecDeque<(i64, i64)>, other: &Groups) {
+    let Some((current, _count)) = other.current else {
+        return;
+    };
+    while pending.front().is_some_and(|(v, _count)| *v < current) {
+        pending.pop_front();
+    }
+}
+
+/// Computes both totals in a single pass over two sorted lists.
+pub fn compare_sorted(
+    list_a: impl Iterator<Item = Result<i64>>,
+    list_b: impl Iterator<Item = Result<i64>>,
+) -> Result<Totals> {
+    let mut distance = 0;
+    let mut matcher = GroupMatcher::default();
+
+    let mut list_b = list_b;
+    for a in list_a {
+        let (a, b) = match list_b.next() {
+            Some(b) => (a?, b?),
+            None => bail!("left list is longer than the right one"),
+        };
+        distance += (a - b).abs();
+        matcher.push(a, b);
+    }
+    if list_b.next().is_some() {
+        bail!("right list is longer than the left one");
+    }
+
+    Ok(Totals {
+        distance,
+        similarity: matcher.finish(),
+    })
+}
+
+/// Reads two whitespace separated columns and compares them, keeping at most
+/// `run_size` numbers per column in memory.
+pub fn compare_external(input: impl BufRead, run_size: usize) -> Result<Totals> {
+    let mut sorter_a = ExternalSorter::new(run_size);
+    let mut sorter_b = ExternalSorter::new(run_size);
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let mut columns = line.split_whitespace();
+        let (Some(a), Some(b), None) = (columns.next(), columns.next(), columns.next()) else {
+            bail!("line {}: expected two columns", i + 1);
+        };
+        let context = || format!("line {}", i + 1);
+        sorter_a.push(a.parse().with_context(context)?)?;
+        sorter_b.push(b.parse().with_context(context)?)?;
+    }
+    compare_sorted(sorter_a.finish()?, sorter_b.finish()?)
+}
+
+/// Reads any number of whitespace separated columns, one list per column.
+pub fn read_lists(input: impl BufRead) -> Result<Vec<Vec<i64>>> {
+    let mut lists: Vec<Vec<i64>> = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let row = line
+            .split_whitespace()
+            .map(|n| n.parse())
+            .collect::<Result<Vec<i64>, _>>()
+            .with_context(|| format!("line {}", i + 1))?;
+        if i == 0 {
+            lists = vec![vec![]; row.len()];
+        } else if row.len() != lists.len() {
+            bail!(
+                "line {}: expected {} columns, got {}",
+                i + 1,
+                lists.len(),
+                row.len()
+            );
+        }
+        for (list, n) in lists.iter_mut().zip(row) {
+            list.push(n);
+        }
+    }
+    Ok(lists)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Metric {
+    /// Sum of `|a - b|` over the sorted pairing.
+    Distance,
+    /// Sum of `(a - b)^2` over the sorted pairing.
+    SquaredDistance,
+    /// Sum of `a * count_right(a)` over the left list.
+    Similarity,
+    /// Size of the multiset intersection over the size of the union.
+    Jaccard,
+    /// Number of elements left over after cancelling out the common ones.
+    SymmetricDifference,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Contribution {
+    pub left: i64,
+    pub right: i64,
+    pub amount: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// The n-th smallest left number paired with the n-th smallest right one,
+    /// which minimises both the distance and the squared distance.
+    pub pairing: Vec<(i64, i64)>,
+    /// value -> (occurences on the left, occurences on the right)
+    pub counts: BTreeMap<i64, (i64, i64)>,
+    pub distance: i64,
+    pub squared_distance: i64,
+    pub similarity: i64,
+    pub jaccard: f64,
+    pub left_only: i64,
+    pub right_only: i64,
+}
+
+impl Report {
+    pub fn new(list_a: &[i64], list_b: &[i64]) -> Result<Self> {
+        if list_a.len() != list_b.len() {
+            bail!(
+                "lists have different lengths: {} and {}",
+                list_a.len(),
+                list_b.len()
+            );
+        }
+        let mut sorted_a = list_a.to_vec();
+        let mut sorted_b = list_b.to_vec();
+        sorted_a.sort_unstable();
+        sorted_b.sort_unstable();
+        let pairing: Vec<_> = sorted_a.into_iter().zip(sorted_b).collect();
+
+        let mut counts = BTreeMap::new();
+        for a in list_a {
+            counts.entry(*a).or_insert((0, 0)).0 += 1;
+        }
+        for b in list_b {
+            counts.entry(*b).or_insert((0, 0)).1 += 1;
+        }
+
+        let distance = pairing.iter().map(|(a, b)| (a - b).abs()).sum();
+        let squared_distance = pairing.iter().map(|(a, b)| (a - b) * (a - b)).sum();
+        let similarity = counts.iter().map(|(v, (ca, cb))| v * ca * cb).sum();
+        let common: i64 = counts.values().map(|(ca, cb)| ca.min(cb)).sum();
+        let all: i64 = counts.values().map(|(ca, cb)| ca.max(cb)).sum();
+        let jaccard = if all == 0 {
+            1.0
+        } else {
+            common as f64 / all as f64
+        };
+
+        Ok(Report {
+            pairing,
+            distance,
+            squared_distance,
+            similarity,
+            jaccard,
+            left_only: list_a.len() as i64 - common,
+            right_only: list_b.len() as i64 - common,
+            counts,
+        })
+    }
+
+    pub fn value(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::Distance => self.distance as f64,
+            Metric::SquaredDistance => self.squared_distance as f64,
+            Metric::Similarity => self.similarity as f64,
+            Metric::Jaccard => self.jaccard,
+            Metric::SymmetricDifference => (self.left_only + self.right_only) as f64,
+        }
+    }
+
+    /// The `k` pairs (for the distance metrics) or values (for the rest)
+    /// which make up the biggest part of the metric.
+    pub fn top_contributors(&self, metric: Metric, k: usize) -> Vec<Contribution> {
+        let mut contributions: Vec<_> = match metric {
+            Metric::Distance | Metric::SquaredDistance => self
+                .pairing
+                .iter()
+                .map(|(a, b)| Contribution {
+                    left: *a,
+                    right: *b,
+                    amount: if metric == Metric::Distance {
+                        (a - b).abs()
+                    } else {
+                        (a - b) * (a - b)
+                    },
+                })
+                .collect(),
+            Metric::Similarity => self
+                .counts
+                .iter()
+                .map(|(v, (ca, cb))| Contribution {
+                    left: *v,
+                    right: *v,
+                    amount: v * ca * cb,
+                })
+                .collect(),
+            Metric::Jaccard | Metric::SymmetricDifference => self
+                .counts
+                .iter()
+                .map(|(v, (ca, cb))| Contribution {
+                    left: *v,
+                    right: *v,
+                    amount: (ca - cb).abs(),
+                })
+                .collect(),
+        };
+        contributions.sort_by_key(|c| Reverse(c.amount.abs()));
+        contributions.truncate(k);
+        contributions
+    }
+
+    pub fn print(&self, metric: Metric, top_k: usize, show_pairing: bool) {
+        if show_pairing {
+            for (a, b) in self.pairing.iter() {
+                println!("{a} {b}");
+            }
+        }
+        println!("{metric}: {}", self.value(metric));
+        if top_k > 0 {
+            println!("top {top_k} contributors:");
+            for c in self.top_contributors(metric, top_k) {
+                println!("  {} {} {}", c.left, c.right, c.amount);
+            }
+        }
+    }
+}
+
+/// `metric` computed between every pair of lists, with the row list on the
+/// left side.
+#[derive(Debug, Clone, Serialize)]
+pub struct Matrix {
+    pub metric: String,
+    pub values: Vec<Vec<f64>>,
+}
+
+impl Matrix {
+    pub fn new(lists: &[Vec<i64>], metric: Metric) -> Result<Self> {
+        let mut values = vec![vec![0.0; lists.len()]; lists.len()];
+        for (i, list_a) in lists.iter().enumerate() {
+            for (j, list_b) in lists.iter().enumerate() {
+                values[i][j] = Report::new(list_a, list_b)?.value(metric);
+            }
+        }
+        Ok(Matrix {
+            metric: metric.to_string(),
+            values,
+        })
+    }
+
+    pub fn print_table(&self) {
+        let cells: Vec<Vec<String>> = self
+            .values
+            .iter()
+            .map(|row| row.iter().map(|v| v.to_string()).collect())
+            .collect();
+        let width = cells
+            .iter()
+            .flatten()
+            .map(|c| c.len())
+            .chain(Some(self.metric.len()))
+            .max()
+            .unwrap_or(0);
+
+        print!("{:>width$}", self.metric);
+        for j in 0..cells.len() {
+            print!(" {j:>width$}");
+        }
+        println!();
+        for (i, row) in cells.iter().enumerate() {
+            print!("{i:>width$}");
+            for c in row {
+                print!(" {c:>width$}");
+            }
+            println!();
+        }
+    }
+
+    pub fn print_json(&self) -> Result<()> {
+        println!("{}", serde_json::to_string(self)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "3   4\n4   3\n2   5\n1   3\n3   9\n3   3\n";
+
+    #[test]
+    fn test_external_matches_in_memory() {
+        let in_memory = compare_external(EXAMPLE.as_bytes(), 1000).unwrap();
+        assert_eq!(
+            in_memory,
+            Totals {
+                distance: 11,
+                similarity: 31
+            }
+        );
+        for run_size in 1..4 {
+            assert_eq!(
+                compare_external(EXAMPLE.as_bytes(), run_size).unwrap(),
+                in_memory
+            );
+        }
+    }
+
+    #[test]
+    fn test_long_run_doesnt_hold_up_groups() {
+        let mut matcher = GroupMatcher::default();
+        for b in 1..=1000 {
+            matcher.push(1000, b);
+            assert!(matcher.pending_b.len() <= 1, "{:?}", matcher.pending_b);
+        }
+        assert_eq!(matcher.finish(), 1000 * 1000);
+
+        let totals = compare_sorted((1..=4).map(Ok), [0, 0, 0, 3].into_iter().map(Ok)).unwrap();
+        assert_eq!(
+            totals,
+            Totals {
+                distance: 7,
+                similarity: 3
+            }
+        );
+        assert!(
+            compare_external("1 2\n3 x\n".as_bytes(), 10)
+                .unwrap_err()
+                .to_string()
+                .contains("line 2")
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let lists = read_lists(EXAMPLE.as_bytes()).unwrap();
+        let report = Report::new(&lists[0], &lists[1]).unwrap();
+        assert_eq!(report.pairing[0], (1, 3));
+        assert_eq!(report.value(Metric::Distance), 11.0);
+        assert_eq!(report.value(Metric::SquaredDistance), 35.0);
+        assert_eq!(report.value(Metric::Similarity), 31.0);
+        // common: 3 x3, 4; union: 1, 2, 3 x3, 4, 5, 9
+        assert_eq!(report.value(Metric::Jaccard), 4.0 / 8.0);
+        assert_eq!(report.value(Metric::SymmetricDifference), 4.0);
+        assert_eq!(
+            report.top_contributors(Metric::Distance, 1),
+            vec![Contribution {
+                left: 4,
+                right: 9,
+                amount: 5
+            }]
+        );
+    }
+
+    #[test]
+    fn test_matrix() {
+        let lists = read_lists("1 2 1\n2 2 1\n3 2 2\n".as_bytes()).unwrap();
+        assert_eq!(lists.len(), 3);
+        let matrix = Matrix::new(&lists, Metric::Distance).unwrap();
+        assert_eq!(
+            matrix.values,
+            vec![
+                vec![0.0, 2.0, 2.0],
+                vec![2.0, 0.0, 2.0],
+                vec![2.0, 2.0, 0.0]
+            ]
+        );
+        assert!(read_lists("1 2\n3\n".as_bytes()).is_err());
+    }
+}
@@ -1,6 +1,13 @@
 // Advent of Code 2024
 // (c) 2024 Mateusz Kwapich
 
+use fixedbitset::FixedBitSet;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    marker::PhantomData,
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Dir {
     Up,
@@ -31,12 +38,12 @@ impl Dir {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Point {
-    x: i64,
-    y: i64,
+    pub x: i64,
+    pub y: i64,
 }
 
 impl Point {
-    fn new(x: i64, y: i64) -> Self {
+    pub fn new(x: i64, y: i64) -> Self {
         Self { x, y }
     }
 
@@ -49,6 +56,15 @@ impl Point {
         ]
     }
 
+    pub fn diagonal_neighbours(&self) -> [Point; 4] {
+        [
+            Self::new(self.x + 1, self.y - 1),
+            Self::new(self.x + 1, self.y + 1),
+            Self::new(self.x - 1, self.y + 1),
+            Self::new(self.x - 1, self.y - 1),
+        ]
+    }
+
     pub fn step(&self, dir: Dir) -> Point {
         match dir {
             Dir::Up => Self::new(self.x, self.y - 1),
@@ -59,6 +75,146 @@ impl Point {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+impl Connectivity {
+    pub fn neighbours(self, p: &Point) -> impl Iterator<Item = Point> {
+        let diagonals = match self {
+            Connectivity::Four => 0,
+            Connectivity::Eight => 4,
+        };
+        p.neighbours()
+            .into_iter()
+            .map(|(n, _dir)| n)
+            .chain(p.diagonal_neighbours().into_iter().take(diagonals))
+    }
+
+    /// Connectivity under which the complement of a region has to be
+    /// traversed so that holes don't leak through diagonal gaps (or get
+    /// split by them).
+    fn dual(self) -> Self {
+        match self {
+            Connectivity::Four => Connectivity::Eight,
+            Connectivity::Eight => Connectivity::Four,
+        }
+    }
+}
+
+/// Connected group of tiles sharing the same label.
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub label: u8,
+    pub points: Vec<Point>,
+    /// Fence segments: a tile of the region and the side of it that faces
+    /// a tile outside of the region.
+    pub perimeter: BTreeSet<(Point, Dir)>,
+    /// Number of straight runs of fence.
+    pub sides: usize,
+    /// Inclusive `(min, max)` corners.
+    pub bounds: (Point, Point),
+    /// Groups of tiles fully enclosed by the region.
+    pub holes: Vec<Vec<Point>>,
+}
+
+impl Region {
+    fn new(
+        label: u8,
+        points: Vec<Point>,
+        perimeter: BTreeSet<(Point, Dir)>,
+        connectivity: Connectivity,
+    ) -> Self {
+        // every side is counted once, at the segment which has no
+        // continuation when walking along the fence clockwise
+        let sides = perimeter
+            .iter()
+            .filter(|(p, dir)| !perimeter.contains(&(p.step(dir.clockwise_rotate()), *dir)))
+            .count();
+
+        let min = Point::new(
+            points.iter().map(|p| p.x).min().unwrap(),
+            points.iter().map(|p| p.y).min().unwrap(),
+        );
+        let max = Point::new(
+            points.iter().map(|p| p.x).max().unwrap(),
+            points.iter().map(|p| p.y).max().unwrap(),
+        );
+        let holes = Self::find_holes(&points, (min, max), connectivity.dual());
+
+        Region {
+            label,
+            points,
+            perimeter,
+            sides,
+            bounds: (min, max),
+            holes,
+        }
+    }
+
+    fn find_holes(
+        points: &[Point],
+        (min, max): (Point, Point),
+        connectivity: Connectivity,
+    ) -> Vec<Vec<Point>> {
+        let inside: HashSet<_> = points.iter().copied().collect();
+        // one tile of margin so that everything touching the outside ends up
+        // in a single component
+        let in_box = |p: &Point| {
+            (min.x - 1..=max.x + 1).contains(&p.x) && (min.y - 1..=max.y + 1).contains(&p.y)
+        };
+        let on_edge = |p: &Point| {
+            p.x == min.x - 1 || p.x == max.x + 1 || p.y == min.y - 1 || p.y == max.y + 1
+        };
+
+        let mut seen = HashSet::new();
+        let mut holes = vec![];
+        for y in min.y - 1..=max.y + 1 {
+            for x in min.x - 1..=max.x + 1 {
+                let p = Point::new(x, y);
+                if inside.contains(&p) || !seen.insert(p) {
+                    continue;
+                }
+                let mut stack = vec![p];
+                let mut component = vec![];
+                let mut enclosed = true;
+                while let Some(p) = stack.pop() {
+                    enclosed &= !on_edge(&p);
+                    for n in connectivity.neighbours(&p) {
+                        if in_box(&n) && !inside.contains(&n) && seen.insert(n) {
+                            stack.push(n);
+                        }
+                    }
+                    component.push(p);
+                }
+                if enclosed {
+                    holes.push(component);
+                }
+            }
+        }
+        holes
+    }
+
+    pub fn area(&self) -> usize {
+        self.points.len()
+    }
+}
+
+/// Tile access shared by the dense `Map` and the sparse `SparseMap`, so that
+/// code can be written once for both.
+pub trait Grid {
+    fn get(&self, p: &Point) -> Option<u8>;
+    fn set(&mut self, p: &Point, val: u8);
+    /// Whether `get` returns a tile for the point.
+    fn contains(&self, p: &Point) -> bool;
+    /// Every stored tile; for a sparse grid the tiles reading as the default
+    /// are left out.
+    fn all_points(&self) -> impl Iterator<Item = (Point, u8)> + '_;
+}
+
+#[derive(Debug, Clone)]
 pub struct Map {
     tiles: Vec<Vec<u8>>,
     h: usize,
@@ -72,6 +228,19 @@ impl Map {
         Map { tiles, h, w }
     }
 
+    /// One row per line.
+    pub fn parse(text: &str) -> Self {
+        Self::new(text.lines().map(|line| line.bytes().collect()).collect())
+    }
+
+    pub fn width(&self) -> usize {
+        self.w
+    }
+
+    pub fn height(&self) -> usize {
+        self.h
+    }
+
     pub fn get(&self, p: &Point) -> Option<u8> {
         let Ok(x) = usize::try_from(p.x) else {
             return None;
@@ -79,8 +248,7 @@ impl Map {
         let Ok(y) = usize::try_from(p.y) else {
             return None;
         };
-        let res = self.tiles.get(y).and_then(|row| row.get(x).copied());
-        res
+        self.tiles.get(y).and_then(|row| row.get(x).copied())
     }
 
     pub fn set(&mut self, p: &Point, val: u8) {
@@ -110,6 +278,34 @@ impl Map {
         })
     }
 
+    pub fn regions(&self, connectivity: Connectivity) -> Vec<Region> {
+        let mut seen = PointSet::for_map(self);
+        let mut regions = vec![];
+        for (p, label) in self.all_points() {
+            if !seen.insert(p) {
+                continue;
+            }
+            let mut stack = vec![p];
+            let mut points = vec![];
+            let mut perimeter = BTreeSet::new();
+            while let Some(p) = stack.pop() {
+                for (n, dir) in p.neighbours() {
+                    if self.get(&n) != Some(label) {
+                        perimeter.insert((p, dir));
+                    }
+                }
+                for n in connectivity.neighbours(&p) {
+                    if self.get(&n) == Some(label) && seen.insert(n) {
+                        stack.push(n);
+                    }
+                }
+                points.push(p);
+            }
+            regions.push(Region::new(label, points, perimeter, connectivity));
+        }
+        regions
+    }
+
     pub fn print(&self) {
         for row in self.tiles.iter() {
             for c in row {
@@ -120,3 +316,421 @@ impl Map {
         }
     }
 }
+
+impl Grid for Map {
+    fn get(&self, p: &Point) -> Option<u8> {
+        Map::get(self, p)
+    }
+
+    fn set(&mut self, p: &Point, val: u8) {
+        Map::set(self, p, val)
+    }
+
+    fn contains(&self, p: &Point) -> bool {
+        Map::contains(self, p)
+    }
+
+    fn all_points(&self) -> impl Iterator<Item = (Point, u8)> + '_ {
+        Map::all_points(self)
+    }
+}
+
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.tiles.iter() {
+            writeln!(f, "{}", String::from_utf8_lossy(row))?;
+        }
+        Ok(())
+    }
+}
+
+/// Set of points on a `w`x`h` grid, stored as one bit per tile.
+#[derive(Debug, Clone)]
+pub struct PointSet {
+    bits: FixedBitSet,
+    w: usize,
+    h: usize,
+}
+
+impl PointSet {
+    pub fn new(w: usize, h: usize) -> Self {
+        PointSet {
+            bits: FixedBitSet::with_capacity(w * h),
+            w,
+            h,
+        }
+    }
+
+    pub fn for_map(map: &Map) -> Self {
+        Self::new(map.w, map.h)
+    }
+
+    fn index(&self, p: &Point) -> Option<usize> {
+        let x = usize::try_from(p.x).ok().filter(|x| *x < self.w)?;
+        let y = usize::try_from(p.y).ok().filter(|y| *y < self.h)?;
+        Some(y * self.w + x)
+    }
+
+    /// Returns whether the point was newly inserted. Panics if the point is
+    /// outside of the grid.
+    pub fn insert(&mut self, p: Point) -> bool {
+        let idx = self.index(&p).expect("point outside of the grid");
+        !self.bits.put(idx)
+    }
+
+    pub fn remove(&mut self, p: &Point) -> bool {
+        match self.index(p) {
+            Some(idx) if self.bits.contains(idx) => {
+                self.bits.set(idx, false);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, p: &Point) -> bool {
+        self.index(p).is_some_and(|idx| self.bits.contains(idx))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones(..)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_clear()
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Point> + '_ {
+        self.bits
+            .ones()
+            .map(|idx| Point::new((idx % self.w) as i64, (idx / self.w) as i64))
+    }
+}
+
+/// Small enums that can be packed next to a point in a `StateSet`.
+pub trait StateIndex: Copy {
+    const COUNT: usize;
+
+    fn index(self) -> usize;
+    fn from_index(idx: usize) -> Self;
+}
+
+impl StateIndex for Dir {
+    const COUNT: usize = 4;
+
+    fn index(self) -> usize {
+        match self {
+            Dir::Up => 0,
+            Dir::Right => 1,
+            Dir::Down => 2,
+            Dir::Left => 3,
+        }
+    }
+
+    fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => Dir::Up,
+            1 => Dir::Right,
+            2 => Dir::Down,
+            3 => Dir::Left,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Set of `(Point, S)` states on a `w`x`h` grid, stored as `S::COUNT` bits
+/// per tile.
+#[derive(Debug, Clone)]
+pub struct StateSet<S> {
+    bits: FixedBitSet,
+    w: usize,
+    h: usize,
+    _state: PhantomData<S>,
+}
+
+impl<S: StateIndex> StateSet<S> {
+    pub fn new(w: usize, h: usize) -> Self {
+        StateSet {
+            bits: FixedBitSet::with_capacity(w * h * S::COUNT),
+            w,
+            h,
+            _state: PhantomData,
+        }
+    }
+
+    pub fn for_map(map: &Map) -> Self {
+        Self::new(map.w, map.h)
+    }
+
+    fn index(&self, (p, s): &(Point, S)) -> Option<usize> {
+        let x = usize::try_from(p.x).ok().filter(|x| *x < self.w)?;
+        let y = usize::try_from(p.y).ok().filter(|y| *y < self.h)?;
+        Some((y * self.w + x) * S::COUNT + s.index())
+    }
+
+    /// Returns whether the state was newly inserted. Panics if the point is
+    /// outside of the grid.
+    pub fn insert(&mut self, state: (Point, S)) -> bool {
+        let idx = self.index(&state).expect("point outside of the grid");
+        !self.bits.put(idx)
+    }
+
+    pub fn remove(&mut self, state: &(Point, S)) -> bool {
+        match self.index(state) {
+            Some(idx) if self.bits.contains(idx) => {
+                self.bits.set(idx, false);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn contains(&self, state: &(Point, S)) -> bool {
+        self.index(state).is_some_and(|idx| self.bits.contains(idx))
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones(..)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.is_clear()
+    }
+
+    pub fn clear(&mut self) {
+        self.bits.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Point, S)> + '_ {
+        self.bits.ones().map(|idx| {
+            let (tile, s) = (idx / S::COUNT, idx % S::COUNT);
+            (
+                Point::new((tile % self.w) as i64, (tile / self.w) as i64),
+                S::from_index(s),
+            )
+        })
+    }
+}
+
+/// Grid backed by a hash map, for puzzles where the interesting tiles are
+/// sparse or the coordinates are unbounded (including negative ones).
+///
+/// Tiles that were never set read as `default` (if there is one). The
+/// bounding box covers every tile that is currently set.
+#[derive(Debug, Clone, Default)]
+pub struct SparseMap {
+    tiles: HashMap<Point, u8>,
+    default: Option<u8>,
+    bounds: Option<(Point, Point)>,
+}
+
+impl SparseMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_default(default: u8) -> Self {
+        SparseMap {
+            default: Some(default),
+            ..Self::default()
+        }
+    }
+
+    /// Builds a sparse map from dense rows, skipping the tiles equal to the
+    /// default one.
+    pub fn from_tiles(tiles: Vec<Vec<u8>>, default: u8) -> Self {
+        let mut map = Self::with_default(default);
+        for (y, row) in tiles.into_iter().enumerate() {
+            for (x, c) in row.into_iter().enumerate() {
+                if c != default {
+                    map.set(&Point::new(x as i64, y as i64), c);
+                }
+            }
+        }
+        map
+    }
+
+    pub fn get(&self, p: &Point) -> Option<u8> {
+        self.tiles.get(p).copied().or(self.default)
+    }
+
+    pub fn set(&mut self, p: &Point, val: u8) {
+        self.tiles.insert(*p, val);
+        self.bounds = Some(extend_bounds(self.bounds, p));
+    }
+
+    pub fn set2(&mut self, p: &Point, val: &[u8; 2]) {
+        self.set(p, val[0]);
+        self.set(&Point::new(p.x + 1, p.y), val[1]);
+    }
+
+    /// Makes the tile read as the default again.
+    pub fn remove(&mut self, p: &Point) -> Option<u8> {
+        let val = self.tiles.remove(p)?;
+        if let Some((min, max)) = self.bounds
+            && (p.x == min.x || p.x == max.x || p.y == min.y || p.y == max.y)
+        {
+            // the removed tile was on the edge, so the box may shrink
+            self.bounds = self
+                .tiles
+                .keys()
+                .fold(None, |bounds, p| Some(extend_bounds(bounds, p)));
+        }
+        Some(val)
+    }
+
+    /// Whether `get` returns a tile for the point: it was set, or there is a
+    /// default.
+    pub fn contains(&self, p: &Point) -> bool {
+        self.default.is_some() || self.is_set(p)
+    }
+
+    /// Whether the tile was explicitly set (as opposed to reading as the
+    /// default).
+    pub fn is_set(&self, p: &Point) -> bool {
+        self.tiles.contains_key(p)
+    }
+
+    /// Inclusive `(min, max)` corners of all the tiles currently set.
+    pub fn bounds(&self) -> Option<(Point, Point)> {
+        self.bounds
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn all_points(&self) -> impl Iterator<Item = (Point, u8)> + '_ {
+        self.tiles.iter().map(|(p, c)| (*p, *c))
+    }
+
+    pub fn print(&self) {
+        let Some((min, max)) = self.bounds else {
+            return;
+        };
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let c = self.get(&Point::new(x, y)).unwrap_or(b' ') as char;
+                print!("{c}");
+            }
+            println!();
+        }
+    }
+}
+
+impl Grid for SparseMap {
+    fn get(&self, p: &Point) -> Option<u8> {
+        SparseMap::get(self, p)
+    }
+
+    fn set(&mut self, p: &Point, val: u8) {
+        SparseMap::set(self, p, val)
+    }
+
+    fn contains(&self, p: &Point) -> bool {
+        SparseMap::contains(self, p)
+    }
+
+    fn all_points(&self) -> impl Iterator<Item = (Point, u8)> + '_ {
+        SparseMap::all_points(self)
+    }
+}
+
+fn extend_bounds(bounds: Option<(Point, Point)>, p: &Point) -> (Point, Point) {
+    match bounds {
+        Some((min, max)) => (
+            Point::new(min.x.min(p.x), min.y.min(p.y)),
+            Point::new(max.x.max(p.x), max.y.max(p.y)),
+        ),
+        None => (*p, *p),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_negative_coords() {
+        let mut map = SparseMap::with_default(b'.');
+        map.set(&Point::new(-3, 2), b'#');
+        map.set(&Point::new(4, -1), b'#');
+        assert_eq!(map.get(&Point::new(-3, 2)), Some(b'#'));
+        assert_eq!(map.get(&Point::new(0, 0)), Some(b'.'));
+        assert!(map.contains(&Point::new(0, 0)));
+        assert!(!map.is_set(&Point::new(0, 0)));
+        assert_eq!(map.bounds(), Some((Point::new(-3, -1), Point::new(4, 2))));
+
+        assert_eq!(map.remove(&Point::new(4, -1)), Some(b'#'));
+        assert_eq!(map.bounds(), Some((Point::new(-3, 2), Point::new(-3, 2))));
+        map.remove(&Point::new(-3, 2));
+        assert_eq!(map.bounds(), None);
+    }
+
+    #[test]
+    fn test_state_set() {
+        let mut set = StateSet::<Dir>::new(3, 2);
+        assert!(set.insert((Point::new(2, 1), Dir::Left)));
+        assert!(!set.insert((Point::new(2, 1), Dir::Left)));
+        assert!(set.insert((Point::new(0, 0), Dir::Up)));
+        assert!(!set.contains(&(Point::new(2, 1), Dir::Up)));
+        assert!(!set.contains(&(Point::new(-1, 0), Dir::Up)));
+        assert_eq!(
+            set.iter().collect::<Vec<_>>(),
+            vec![(Point::new(0, 0), Dir::Up), (Point::new(2, 1), Dir::Left)]
+        );
+    }
+
+    #[test]
+    fn test_regions() {
+        let map = Map::parse("AAAAA\nABABA\nAAAAA\nCCACC");
+        let regions = map.regions(Connectivity::Four);
+        assert_eq!(regions.len(), 5);
+        let a = &regions[0];
+        assert_eq!((a.label, a.area()), (b'A', 14));
+        assert_eq!(a.perimeter.len(), 26);
+        assert_eq!(a.sides, 16);
+        assert_eq!(a.bounds, (Point::new(0, 0), Point::new(4, 3)));
+        assert_eq!(a.holes.len(), 2);
+
+        let regions = map.regions(Connectivity::Eight);
+        assert_eq!(regions.len(), 5);
+        assert_eq!(regions[1].label, b'B');
+        assert!(regions[1].holes.is_empty());
+    }
+
+    #[test]
+    fn test_sparse_no_default() {
+        let map = SparseMap::from_tiles(vec![b"..#".to_vec(), b"#..".to_vec()], b'.');
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&Point::new(2, 0)), Some(b'#'));
+        assert_eq!(SparseMap::new().get(&Point::new(2, 0)), None);
+        assert!(!SparseMap::new().contains(&Point::new(2, 0)));
+    }
+
+    fn count<G: Grid>(grid: &G, val: u8) -> usize {
+        grid.all_points().filter(|(_p, c)| *c == val).count()
+    }
+
+    #[test]
+    fn test_grid() {
+        let tiles = vec![b"..#".to_vec(), b"#.#".to_vec()];
+        let mut dense = Map::new(tiles.clone());
+        let mut sparse = SparseMap::from_tiles(tiles, b'.');
+        Grid::set(&mut dense, &Point::new(1, 1), b'#');
+        Grid::set(&mut sparse, &Point::new(1, 1), b'#');
+        assert_eq!(count(&dense, b'#'), 4);
+        assert_eq!(count(&sparse, b'#'), 4);
+        let p = Point::new(0, 0);
+        assert_eq!(Grid::get(&dense, &p), Grid::get(&sparse, &p));
+        assert!(Grid::contains(&dense, &p) && Grid::contains(&sparse, &p));
+    }
+}
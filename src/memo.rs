@@ -0,0 +1,229 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use anyhow::{Result, bail};
+use serde::{Serialize, de::DeserializeOwned};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::File,
+    hash::Hash,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub len: usize,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} entries, {} hits, {} misses ({:.1}% hit rate), {} evictions",
+            self.len,
+            self.hits,
+            self.misses,
+            self.hit_rate() * 100.0,
+            self.evictions
+        )
+    }
+}
+
+/// Memoization cache owned by a solver, so that separate inputs don't share
+/// state. When a capacity is set the least recently used entries are evicted.
+#[derive(Debug, Clone)]
+pub struct Cache<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    // last use tick -> key, to find the least recently used entry quickly
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+    capacity: Option<usize>,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Default for Cache<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Cache<K, V> {
+    pub fn new() -> Self {
+        Cache {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            capacity: None,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Result<Self> {
+        if capacity == 0 {
+            bail!("cache capacity must be positive");
+        }
+        Ok(Cache {
+            capacity: Some(capacity),
+            ..Self::new()
+        })
+    }
+
+    fn touch(&mut self, key: &K) {
+        self.tick += 1;
+        if let Some((_v, last_used)) = self.entries.get_mut(key) {
+            self.recency.remove(last_used);
+            *last_used = self.tick;
+            self.recency.insert(self.tick, key.clone());
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        if self.entries.contains_key(key) {
+            self.stats.hits += 1;
+            self.touch(key);
+            self.entries.get(key).map(|(v, _last_used)| v.clone())
+        } else {
+            self.stats.misses += 1;
+            None
+        }
+    }
+
+    pub fn insert(&mut self, key: K, val: V) {
+        if let Some((old, _last_used)) = self.entries.get_mut(&key) {
+            *old = val;
+            self.touch(&key);
+            return;
+        }
+        if let Some(capacity) = self.capacity {
+            while self.entries.len() >= capacity {
+                let (_tick, oldest) = self.recency.pop_first().unwrap();
+                self.entries.remove(&oldest);
+                self.stats.evictions += 1;
+            }
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (val, self.tick));
+    }
+
+    /// For non-recursive computations; recursive ones need to `get` and
+    /// `insert` themselves as `compute` can't borrow the cache.
+    pub fn get_or_insert_with<F: FnOnce() -> V>(&mut self, key: K, compute: F) -> V {
+        if let Some(val) = self.get(&key) {
+            return val;
+        }
+        let val = compute();
+        self.insert(key, val.clone());
+        val
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drops all the entries but keeps the statistics.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    /// Drops all the entries and the statistics.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.stats = CacheStats::default();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            len: self.entries.len(),
+            ..self.stats
+        }
+    }
+}
+
+impl<K, V> Cache<K, V>
+where
+    K: Clone + Eq + Hash + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    /// Writes the entries (least recently used first) as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let entries: Vec<(&K, &V)> = self
+            .recency
+            .values()
+            .map(|k| (k, &self.entries[k].0))
+            .collect();
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &entries)?;
+        Ok(())
+    }
+
+    /// Adds the entries saved by `save` to the cache.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let entries: Vec<(K, V)> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        for (k, v) in entries {
+            self.insert(k, v);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        assert!(Cache::<u64, u64>::with_capacity(0).is_err());
+        let mut cache = Cache::with_capacity(2).unwrap();
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some("a"));
+        assert_eq!(cache.get(&3), Some("c"));
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 3,
+                misses: 1,
+                evictions: 1,
+                len: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = std::env::temp_dir().join(format!("memo_test_{}.json", std::process::id()));
+        let mut cache = Cache::new();
+        cache.insert((1_u64, 2_usize), 3_usize);
+        cache.insert((4, 5), 6);
+        cache.save(&path).unwrap();
+
+        let mut loaded = Cache::new();
+        loaded.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.get(&(4, 5)), Some(6));
+    }
+}
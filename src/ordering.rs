@@ -0,0 +1,630 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use anyhow::{Context, Result, anyhow};
+use itertools::Itertools;
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    io::{BufRead, Write},
+};
+
+/// Set of `X|Y` rules, each saying that page `X` has to be printed before
+/// page `Y`, which can change while queries are answered.
+///
+/// Rules can be added and removed at will, so nothing assumes that every pair
+/// of pages in an update is covered by a rule, or that the rules are acyclic.
+///
+/// Rules are indexed by their first page, so checking or fixing an update of
+/// `k` distinct pages takes O(k log k + r), where `r` is the number of rules
+/// starting at one of those pages.
+#[derive(Debug, Clone, Default)]
+pub struct PageOrdering {
+    /// Pages which have to come after the key.
+    after: HashMap<i64, HashSet<i64>>,
+    len: usize,
+}
+
+impl PageOrdering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false if the rule was already there.
+    pub fn add(&mut self, before: i64, after: i64) -> bool {
+        let added = self.after.entry(before).or_default().insert(after);
+        self.len += added as usize;
+        added
+    }
+
+    /// Returns false if there was no such rule.
+    pub fn remove(&mut self, before: i64, after: i64) -> bool {
+        let Some(pages) = self.after.get_mut(&before) else {
+            return false;
+        };
+        if !pages.remove(&after) {
+            return false;
+        }
+        if pages.is_empty() {
+            self.after.remove(&before);
+        }
+        self.len -= 1;
+        true
+    }
+
+    pub fn contains(&self, before: i64, after: i64) -> bool {
+        self.after
+            .get(&before)
+            .is_some_and(|pages| pages.contains(&after))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Pages which have to come after `before`, in no particular order.
+    pub fn successors(&self, before: i64) -> impl Iterator<Item = i64> + '_ {
+        self.after.get(&before).into_iter().flatten().copied()
+    }
+
+    /// Whether the update breaks no rule, including rules between pages
+    /// which aren't next to each other.
+    pub fn is_valid(&self, pages: &[i64]) -> bool {
+        let position = positions(pages);
+        pages.iter().enumerate().all(|(i, before)| {
+            self.successors(*before)
+                .all(|after| position.get(&after).is_none_or(|j| *j >= i))
+        })
+    }
+
+    /// Successors of each page restricted to the update, as indices.
+    fn edges(&self, pages: &[i64]) -> Vec<Vec<usize>> {
+        let position = positions(pages);
+        pages
+            .iter()
+            .map(|before| {
+                self.successors(*before)
+                    .filter_map(|after| position.get(&after).copied())
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+fn positions(pages: &[i64]) -> HashMap<i64, usize> {
+    pages
+        .iter()
+        .enumerate()
+        .map(|(i, page)| (*page, i))
+        .collect()
+}
+
+impl FromIterator<(i64, i64)> for PageOrdering {
+    fn from_iter<I: IntoIterator<Item = (i64, i64)>>(rules: I) -> Self {
+        let mut ordering = PageOrdering::new();
+        ordering.extend(rules);
+        ordering
+    }
+}
+
+impl Extend<(i64, i64)> for PageOrdering {
+    fn extend<I: IntoIterator<Item = (i64, i64)>>(&mut self, rules: I) {
+        for (before, after) in rules {
+            self.add(before, after);
+        }
+    }
+}
+
+pub fn parse_rule(rule: &str) -> Result<(i64, i64)> {
+    let (before, after) = rule
+        .split_once('|')
+        .with_context(|| format!("expected X|Y, got {rule:?}"))?;
+    Ok((before.trim().parse()?, after.trim().parse()?))
+}
+
+pub fn parse_update(update: &str) -> Result<Vec<i64>> {
+    update.split(',').map(|n| Ok(n.trim().parse()?)).collect()
+}
+
+/// Answers one command per line until the input ends:
+///
+/// - `add X|Y`, `remove X|Y` change the rules,
+/// - `check a,b,c` prints whether the update is valid,
+/// - `fix a,b,c` prints the update in the correct order, or the cycle which
+///   makes that impossible,
+/// - `rules` prints how many rules there are.
+///
+/// A malformed command gets an `error: ...` reply and doesn't stop the
+/// session.
+pub fn serve(
+    ordering: &mut PageOrdering,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let reply = match command {
+            "add" => parse_rule(arg).map(|(a, b)| match ordering.add(a, b) {
+                true => "added".to_string(),
+                false => "already present".to_string(),
+            }),
+            "remove" => parse_rule(arg).map(|(a, b)| match ordering.remove(a, b) {
+                true => "removed".to_string(),
+                false => "not present".to_string(),
+            }),
+            "check" => parse_update(arg).map(|pages| match ordering.is_valid(&pages) {
+                true => "valid".to_string(),
+                false => "invalid".to_string(),
+            }),
+            "fix" => {
+                parse_update(arg).and_then(|pages| match topological_order(ordering, &pages) {
+                    Order::Cycle(cycle) => Err(anyhow!("cycle {}", cycle.iter().join(","))),
+                    order => Ok(order.pages().unwrap().iter().join(",")),
+                })
+            }
+            "rules" => Ok(ordering.len().to_string()),
+            _ => Err(anyhow!("unknown command {command:?}")),
+        };
+        match reply {
+            Ok(reply) => writeln!(output, "{reply}")?,
+            Err(err) => writeln!(output, "error: {err}")?,
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+/// Result of ordering an update's pages by the `X|Y` rules that mention
+/// only pages from that update.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Order {
+    /// The rules force exactly this order.
+    Unique(Vec<i64>),
+    /// A valid order, but the rules don't decide between `choice.0` and
+    /// `choice.1` (the first such pair found).
+    Ambiguous { order: Vec<i64>, choice: (i64, i64) },
+    /// The rules contradict each other: each page has to come before the
+    /// next one, and the last before the first.
+    Cycle(Vec<i64>),
+}
+
+impl Order {
+    pub fn pages(&self) -> Option<&[i64]> {
+        match self {
+            Order::Unique(order) | Order::Ambiguous { order, .. } => Some(order),
+            Order::Cycle(_) => None,
+        }
+    }
+}
+
+/// Kahn's algorithm over the precedence graph restricted to `pages`. When
+/// several pages are ready the one earliest in the update goes first, so
+/// already valid updates keep their order.
+///
+/// If the rules totally order the pages, as in the puzzle input, the order is
+/// found by sorting on the number of successors instead.
+pub fn topological_order(rules: &PageOrdering, pages: &[i64]) -> Order {
+    let k = pages.len();
+    let succ = rules.edges(pages);
+    if let Some(order) = total_order(&succ) {
+        return Order::Unique(order.into_iter().map(|i| pages[i]).collect());
+    }
+
+    let mut in_degree = vec![0; k];
+    for j in succ.iter().flatten() {
+        in_degree[*j] += 1;
+    }
+
+    let mut ready: BTreeSet<usize> = (0..k).filter(|i| in_degree[*i] == 0).collect();
+    let mut order = vec![];
+    let mut choice = None;
+    while let Some(i) = ready.pop_first() {
+        if choice.is_none()
+            && let Some(j) = ready.first()
+        {
+            choice = Some((pages[i], pages[*j]));
+        }
+        order.push(pages[i]);
+        for j in succ[i].iter() {
+            in_degree[*j] -= 1;
+            if in_degree[*j] == 0 {
+                ready.insert(*j);
+            }
+        }
+    }
+
+    if order.len() < k {
+        return Order::Cycle(find_cycle(&succ, &in_degree, pages));
+    }
+    match choice {
+        Some(choice) => Order::Ambiguous { order, choice },
+        None => Order::Unique(order),
+    }
+}
+
+/// In a total order of `k` pages the first one has `k - 1` successors, the
+/// next `k - 2` and so on, and every edge points forward.
+fn total_order(succ: &[Vec<usize>]) -> Option<Vec<usize>> {
+    let k = succ.len();
+    let mut order: Vec<usize> = (0..k).collect();
+    order.sort_by_key(|i| Reverse(succ[*i].len()));
+    let mut position = vec![0; k];
+    for (p, i) in order.iter().enumerate() {
+        if succ[*i].len() != k - 1 - p {
+            return None;
+        }
+        position[*i] = p;
+    }
+    (0..k)
+        .all(|i| succ[i].iter().all(|j| position[*j] > position[i]))
+        .then_some(order)
+}
+
+/// Every page left over by Kahn's algorithm has a leftover predecessor, so
+/// walking predecessors from any of them has to run into a cycle.
+fn find_cycle(succ: &[Vec<usize>], in_degree: &[usize], pages: &[i64]) -> Vec<i64> {
+    let stuck = |i: usize| in_degree[i] > 0;
+    let mut pred = vec![None; pages.len()];
+    for i in (0..pages.len()).rev().filter(|i| stuck(*i)) {
+        for j in succ[i].iter() {
+            pred[*j] = Some(i);
+        }
+    }
+    let mut path = vec![(0..pages.len()).find(|i| stuck(*i)).unwrap()];
+    loop {
+        let p = pred[*path.last().unwrap()].unwrap();
+        if let Some(pos) = path.iter().position(|i| *i == p) {
+            // path goes backwards along the edges
+            return path[pos..].iter().rev().map(|i| pages[*i]).collect();
+        }
+        path.push(p);
+    }
+}
+
+/// Rule `before|after` broken by `after` appearing earlier in the update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RuleViolation {
+    pub before: i64,
+    pub after: i64,
+    /// Indices of `before` and `after` in the update.
+    pub positions: (usize, usize),
+}
+
+/// Every rule broken by an update, not just by neighbouring pages.
+pub fn violations(rules: &PageOrdering, pages: &[i64]) -> Vec<RuleViolation> {
+    let mut found = vec![];
+    for (j, succ) in rules.edges(pages).into_iter().enumerate() {
+        for i in succ.into_iter().filter(|i| *i < j) {
+            found.push(RuleViolation {
+                before: pages[j],
+                after: pages[i],
+                positions: (j, i),
+            });
+        }
+    }
+    found.sort_by_key(|v| (v.positions.1, v.positions.0));
+    found
+}
+
+/// Fewest pages which have to be moved elsewhere in the update to make it
+/// valid, or `None` if the rules form a cycle.
+///
+/// Pages `i < j` can't both stay in place if `pages[j]` has to come before
+/// `pages[i]`, directly or through other pages. That relation is a partial
+/// order, the pages staying in place are an antichain of it, and by
+/// Dilworth's theorem the number of pages left to move is the size of a
+/// maximum matching between its smaller and greater elements.
+pub fn min_moves(rules: &PageOrdering, pages: &[i64]) -> Option<usize> {
+    let k = pages.len();
+    let mut reach = vec![vec![false; k]; k];
+    for (i, a) in pages.iter().enumerate() {
+        for (j, b) in pages.iter().enumerate() {
+            reach[i][j] = rules.contains(*a, *b);
+        }
+    }
+    for m in 0..k {
+        let via = reach[m].clone();
+        for row in reach.iter_mut().filter(|row| row[m]) {
+            for (r, v) in row.iter_mut().zip(via.iter()) {
+                *r |= *v;
+            }
+        }
+    }
+    if (0..k).any(|i| reach[i][i]) {
+        return None;
+    }
+
+    let conflicts: Vec<Vec<usize>> = (0..k)
+        .map(|i| (i + 1..k).filter(|j| reach[*j][i]).collect())
+        .collect();
+    let mut matched: Vec<Option<usize>> = vec![None; k];
+    let mut moves = 0;
+    for i in 0..k {
+        let mut seen = vec![false; k];
+        if augment(i, &conflicts, &mut matched, &mut seen) {
+            moves += 1;
+        }
+    }
+    Some(moves)
+}
+
+/// Kuhn's augmenting path search.
+fn augment(
+    i: usize,
+    edges: &[Vec<usize>],
+    matched: &mut [Option<usize>],
+    seen: &mut [bool],
+) -> bool {
+    for j in edges[i].iter() {
+        if seen[*j] {
+            continue;
+        }
+        seen[*j] = true;
+        if matched[*j].is_none_or(|m| augment(m, edges, matched, seen)) {
+            matched[*j] = Some(i);
+            return true;
+        }
+    }
+    false
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Explanation {
+    /// 1-based index of the update in the input.
+    pub update: usize,
+    pub pages: Vec<i64>,
+    pub violations: Vec<RuleViolation>,
+    pub moves: Option<usize>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "update {}: {}: violates {}",
+            self.update,
+            self.pages.iter().join(","),
+            self.violations
+                .iter()
+                .map(|v| format!(
+                    "{}|{} (at {} and {})",
+                    v.before, v.after, v.positions.0, v.positions.1
+                ))
+                .join(", ")
+        )?;
+        match self.moves {
+            Some(moves) => write!(f, "; fix: {moves} moves"),
+            None => write!(f, "; no fix, the rules form a cycle"),
+        }
+    }
+}
+
+/// Explains why the update is invalid, or returns `None` if it's valid.
+pub fn explain(rules: &PageOrdering, update: usize, pages: &[i64]) -> Option<Explanation> {
+    let violations = violations(rules, pages);
+    if violations.is_empty() {
+        return None;
+    }
+    Some(Explanation {
+        update,
+        pages: pages.to_vec(),
+        violations,
+        moves: min_moves(rules, pages),
+    })
+}
+
+pub fn print_explanations(explanations: &[Explanation], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(explanations)?);
+    } else {
+        for explanation in explanations {
+            println!("{explanation}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::Lcg;
+
+    #[test]
+    fn test_unique() {
+        let rules: PageOrdering = [(1, 2), (2, 3), (1, 3)].into_iter().collect();
+        assert_eq!(
+            topological_order(&rules, &[3, 1, 2]),
+            Order::Unique(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn test_successor_counts() {
+        // 1 has two successors and 2 one, like a total order, but 2|1 points
+        // backwards, so this is a cycle rather than a sort.
+        let mut rules: PageOrdering = [(1, 2), (1, 3), (2, 1)].into_iter().collect();
+        assert!(matches!(
+            topological_order(&rules, &[1, 2, 3]),
+            Order::Cycle(_)
+        ));
+        assert!(rules.remove(2, 1));
+        assert!(!rules.remove(2, 1));
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules.successors(2).count(), 0);
+        rules.add(2, 3);
+        assert_eq!(
+            topological_order(&rules, &[3, 2, 1]),
+            Order::Unique(vec![1, 2, 3])
+        );
+        assert!(rules.is_valid(&[1, 2, 3]));
+        assert!(!rules.is_valid(&[1, 3, 2]));
+    }
+
+    #[test]
+    fn test_ambiguous() {
+        let rules: PageOrdering = [(1, 2), (1, 3)].into_iter().collect();
+        assert_eq!(
+            topological_order(&rules, &[3, 1, 2]),
+            Order::Ambiguous {
+                order: vec![1, 3, 2],
+                choice: (3, 2)
+            }
+        );
+    }
+
+    #[test]
+    fn test_cycle() {
+        let rules: PageOrdering = [(5, 1), (1, 2), (2, 3), (3, 1), (9, 5)]
+            .into_iter()
+            .collect();
+        let Order::Cycle(cycle) = topological_order(&rules, &[5, 1, 2, 3]) else {
+            panic!("expected a cycle");
+        };
+        assert_eq!(cycle.len(), 3);
+        for (a, b) in cycle.iter().zip(cycle.iter().cycle().skip(1)) {
+            assert!(rules.contains(*a, *b), "{a}|{b}");
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let rules: PageOrdering = [
+            (97, 13),
+            (97, 75),
+            (97, 29),
+            (97, 47),
+            (75, 13),
+            (75, 29),
+            (75, 47),
+            (47, 29),
+            (47, 13),
+            (29, 13),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(explain(&rules, 1, &[97, 75, 47, 29, 13]), None);
+
+        let explanation = explain(&rules, 2, &[97, 13, 75, 29, 47]).unwrap();
+        assert_eq!(
+            explanation
+                .violations
+                .iter()
+                .map(|v| (v.before, v.after))
+                .collect_vec(),
+            vec![(75, 13), (29, 13), (47, 13), (47, 29)]
+        );
+        assert_eq!(explanation.moves, Some(2));
+
+        // 29|47 only follows through 75
+        let rules: PageOrdering = [(29, 75), (75, 47)].into_iter().collect();
+        assert_eq!(violations(&rules, &[47, 29]), vec![]);
+        assert_eq!(min_moves(&rules, &[47, 75, 29]), Some(2));
+        assert_eq!(min_moves(&rules, &[47, 29, 75]), Some(1));
+    }
+
+    #[test]
+    fn test_min_moves_brute_force() {
+        let mut rng = Lcg::new(7);
+        for _ in 0..200 {
+            // rules between random pairs, all pointing up so there's no cycle
+            let mut rules = PageOrdering::new();
+            for _ in 0..rng.below(12) {
+                let (a, b) = (rng.below(6) as i64, rng.below(6) as i64);
+                if a < b {
+                    rules.add(a, b);
+                }
+            }
+            let mut pages = (0..6).collect_vec();
+            for i in (1..pages.len()).rev() {
+                pages.swap(i, rng.below(i as u64 + 1) as usize);
+            }
+
+            // the most pages that can stay put, keeping their relative order
+            let kept = (0..1_u32 << pages.len())
+                .filter(|mask| {
+                    let mut chained = rules.clone();
+                    let stay = (0..pages.len())
+                        .filter(|i| mask & (1 << i) != 0)
+                        .map(|i| pages[i])
+                        .collect_vec();
+                    chained.extend(stay.windows(2).map(|w| (w[0], w[1])));
+                    topological_order(&chained, &pages).pages().is_some()
+                })
+                .map(|mask| mask.count_ones() as usize)
+                .max()
+                .unwrap();
+            assert_eq!(
+                min_moves(&rules, &pages),
+                Some(pages.len() - kept),
+                "{rules:?} {pages:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut ordering = PageOrdering::new();
+        let commands = "add 47|53\nadd 97|47\nadd 97|53\ncheck 97,47,53\nfix 53,47,97\n\
+                        remove 97|47\nremove 97|47\nrules\nadd 1-2\njump";
+        let mut output = vec![];
+        serve(&mut ordering, commands.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap().lines().collect_vec(),
+            vec![
+                "added",
+                "added",
+                "added",
+                "valid",
+                "97,47,53",
+                "removed",
+                "not present",
+                "2",
+                "error: expected X|Y, got \"1-2\"",
+                "error: unknown command \"jump\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_serve_partial_rules() {
+        let mut ordering = PageOrdering::new();
+        let commands = "add 1|3\ncheck 3,2,1\nfix 3,2,1\ncheck 1,2,3";
+        let mut output = vec![];
+        serve(&mut ordering, commands.as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap().lines().collect_vec(),
+            vec!["added", "invalid", "2,1,3", "valid"]
+        );
+    }
+
+    #[test]
+    fn test_serve_cyclic_rules() {
+        let mut ordering = PageOrdering::new();
+        let mut commands = "add 1|2\nadd 2|3\nadd 3|1\ncheck 1,2,3\nfix 1,2,3\n".to_string();
+        // a long cycle used to break the sort comparator's total order
+        for page in 0..40 {
+            commands += &format!("add {}|{}\n", page + 10, (page + 1) % 40 + 10);
+        }
+        commands += &format!("fix {}", (10..50).rev().join(","));
+        let mut output = vec![];
+        serve(&mut ordering, commands.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let replies = output.lines().collect_vec();
+        assert_eq!(
+            replies[..5],
+            ["added", "added", "added", "invalid", "error: cycle 2,3,1"]
+        );
+        assert!(replies[45].starts_with("error: cycle "), "{}", replies[45]);
+    }
+}
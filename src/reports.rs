@@ -0,0 +1,394 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use crate::args::{arg_value, has_flag, parse_arg};
+use anyhow::{Context, Result, bail};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fmt, fs::File, io::BufReader};
+use strum::{Display, EnumString};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Order {
+    Decreasing,
+    Increasing,
+}
+
+/// Which orders a safe report may follow.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Direction {
+    Increasing,
+    Decreasing,
+    /// Either one, as long as it's the same for the whole report.
+    Either,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SafetyPolicy {
+    pub min_step: i64,
+    pub max_step: i64,
+    pub direction: Direction,
+    /// Whether equal neighbours are allowed regardless of the other rules.
+    pub allow_equal: bool,
+    /// How many levels can be removed from a report to make it safe.
+    pub dampener: usize,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        SafetyPolicy {
+            min_step: 1,
+            max_step: 3,
+            direction: Direction::Either,
+            allow_equal: false,
+            dampener: 0,
+        }
+    }
+}
+
+/// Policy fields that are set by a config file or on the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyOverrides {
+    min_step: Option<i64>,
+    max_step: Option<i64>,
+    direction: Option<Direction>,
+    allow_equal: Option<bool>,
+    dampener: Option<usize>,
+}
+
+impl SafetyPolicy {
+    /// Overrides the rules with the ones from a JSON file passed with
+    /// `--policy`, and then with `--min-step`, `--max-step`, `--direction`,
+    /// `--allow-equal` and `--dampener` flags.
+    pub fn with_args(self, args: &[String]) -> Result<Self> {
+        let mut policy = self;
+        if let Some(path) = arg_value(args, "--policy") {
+            let file = File::open(path).with_context(|| format!("opening {path}"))?;
+            let overrides = serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("parsing {path}"))?;
+            policy.apply(overrides);
+        }
+
+        let overrides = PolicyOverrides {
+            min_step: parse_arg(args, "--min-step")?,
+            max_step: parse_arg(args, "--max-step")?,
+            direction: parse_arg(args, "--direction")?,
+            allow_equal: has_flag(args, "--allow-equal").then_some(true),
+            dampener: parse_arg(args, "--dampener")?,
+        };
+        policy.apply(overrides);
+
+        if policy.min_step < 1 || policy.min_step > policy.max_step {
+            bail!(
+                "invalid step range {}..={}",
+                policy.min_step,
+                policy.max_step
+            );
+        }
+        Ok(policy)
+    }
+
+    fn apply(&mut self, overrides: PolicyOverrides) {
+        self.min_step = overrides.min_step.unwrap_or(self.min_step);
+        self.max_step = overrides.max_step.unwrap_or(self.max_step);
+        self.direction = overrides.direction.unwrap_or(self.direction);
+        self.allow_equal = overrides.allow_equal.unwrap_or(self.allow_equal);
+        self.dampener = overrides.dampener.unwrap_or(self.dampener);
+    }
+
+    fn orders(&self) -> &'static [Order] {
+        match self.direction {
+            Direction::Increasing => &[Order::Increasing],
+            Direction::Decreasing => &[Order::Decreasing],
+            Direction::Either => &[Order::Increasing, Order::Decreasing],
+        }
+    }
+
+    pub fn valid_step(&self, a: i64, b: i64, order: Order) -> bool {
+        if a == b {
+            return self.allow_equal;
+        }
+        let step = match order {
+            Order::Increasing => b - a,
+            Order::Decreasing => a - b,
+        };
+        (self.min_step..=self.max_step).contains(&step)
+    }
+
+    /// Longest subsequence in which all the neighbours are valid steps in
+    /// given order, as indices into `seq`. Gaps longer than the dampener are
+    /// not considered as they would need removing too many levels anyway.
+    fn longest_valid(&self, seq: &[i64], order: Order) -> Vec<usize> {
+        // len[i]: length of the longest valid subsequence ending at i
+        let mut len = vec![1; seq.len()];
+        let mut prev = vec![None; seq.len()];
+        for i in 0..seq.len() {
+            for j in i.saturating_sub(self.dampener + 1)..i {
+                if self.valid_step(seq[j], seq[i], order) && len[j] + 1 > len[i] {
+                    len[i] = len[j] + 1;
+                    prev[i] = Some(j);
+                }
+            }
+        }
+
+        let Some(mut i) = (0..seq.len()).max_by_key(|i| len[*i]) else {
+            return vec![];
+        };
+        let mut kept = vec![i];
+        while let Some(j) = prev[i] {
+            kept.push(j);
+            i = j;
+        }
+        kept.reverse();
+        kept
+    }
+
+    /// Indices of the fewest levels that have to be removed to make the
+    /// report safe, or `None` if that takes more than the dampener allows.
+    pub fn levels_to_remove(&self, seq: &[i64]) -> Option<Vec<usize>> {
+        self.orders()
+            .iter()
+            .map(|order| self.longest_valid(seq, *order))
+            .max_by_key(|kept| kept.len())
+            .filter(|kept| seq.len() - kept.len() <= self.dampener)
+            .map(|kept| (0..seq.len()).filter(|i| !kept.contains(i)).collect())
+    }
+
+    pub fn is_safe(&self, seq: &[i64]) -> bool {
+        self.levels_to_remove(seq).is_some()
+    }
+}
+
+/// Why a pair of neighbouring levels breaks the rules.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Display)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Violation {
+    StepTooLarge,
+    StepTooSmall,
+    Plateau,
+    /// The report changes between increasing and decreasing.
+    DirectionFlip,
+    /// The report goes in a direction the policy doesn't allow.
+    WrongDirection,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnosis {
+    pub line: usize,
+    pub levels: Vec<i64>,
+    /// Index of the first level that breaks the rules with its predecessor.
+    pub index: usize,
+    pub violation: Violation,
+    /// Fewest levels to remove to make the report safe, ignoring the
+    /// dampener, as long as at least two levels are left.
+    pub fix: Option<Vec<usize>>,
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {}: {} at index {} ({} -> {})",
+            self.line,
+            self.levels.iter().join(" "),
+            self.violation,
+            self.index,
+            self.levels[self.index - 1],
+            self.levels[self.index]
+        )?;
+        match &self.fix {
+            Some(fix) => write!(f, "; fix: remove levels at {}", fix.iter().join(", ")),
+            None => write!(f, "; no fix"),
+        }
+    }
+}
+
+impl SafetyPolicy {
+    /// The first pair of neighbours breaking the rules, regardless of the
+    /// dampener.
+    pub fn first_violation(&self, seq: &[i64]) -> Option<(usize, Violation)> {
+        let mut order = match self.direction {
+            Direction::Increasing => Some(Order::Increasing),
+            Direction::Decreasing => Some(Order::Decreasing),
+            Direction::Either => None,
+        };
+        for (i, w) in seq.windows(2).enumerate() {
+            let (a, b) = (w[0], w[1]);
+            let step_order = match b.cmp(&a) {
+                Ordering::Equal if self.allow_equal => continue,
+                Ordering::Equal => return Some((i + 1, Violation::Plateau)),
+                Ordering::Greater => Order::Increasing,
+                Ordering::Less => Order::Decreasing,
+            };
+            match order {
+                Some(order) if order != step_order && self.direction == Direction::Either => {
+                    return Some((i + 1, Violation::DirectionFlip));
+                }
+                Some(order) if order != step_order => {
+                    return Some((i + 1, Violation::WrongDirection));
+                }
+                _ => {
+                    order = Some(step_order);
+                }
+            }
+            let step = (b - a).abs();
+            if step > self.max_step {
+                return Some((i + 1, Violation::StepTooLarge));
+            }
+            if step < self.min_step {
+                return Some((i + 1, Violation::StepTooSmall));
+            }
+        }
+        None
+    }
+
+    /// Explains why the report is unsafe, or returns `None` if it's safe.
+    pub fn diagnose(&self, line: usize, seq: &[i64]) -> Option<Diagnosis> {
+        if self.is_safe(seq) {
+            return None;
+        }
+        let (index, violation) = self.first_violation(seq)?;
+        let unlimited = SafetyPolicy {
+            dampener: seq.len(),
+            ..self.clone()
+        };
+        let fix = unlimited
+            .levels_to_remove(seq)
+            .filter(|fix| seq.len() - fix.len() >= 2);
+        Some(Diagnosis {
+            line,
+            levels: seq.to_vec(),
+            index,
+            violation,
+            fix,
+        })
+    }
+}
+
+pub fn print_diagnoses(diagnoses: &[Diagnosis], json: bool) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(diagnoses)?);
+    } else {
+        for diagnosis in diagnoses {
+            println!("{diagnosis}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lcg::Lcg;
+
+    fn brute_force(policy: &SafetyPolicy, seq: &[i64]) -> Option<usize> {
+        (0..(1_u32 << seq.len()))
+            .filter(|removed| removed.count_ones() as usize <= policy.dampener)
+            .filter(|removed| {
+                let kept: Vec<_> = (0..seq.len())
+                    .filter(|i| removed & (1 << i) == 0)
+                    .map(|i| seq[i])
+                    .collect();
+                policy.orders().iter().any(|order| {
+                    kept.windows(2)
+                        .all(|w| policy.valid_step(w[0], w[1], *order))
+                })
+            })
+            .map(|removed| removed.count_ones() as usize)
+            .min()
+    }
+
+    #[test]
+    fn test_matches_brute_force() {
+        let mut rng = Lcg::new(2024);
+        for _i in 0..500 {
+            let mut seq = vec![];
+            for _j in 0..rng.below(8) {
+                seq.push(rng.below(7) as i64);
+            }
+            for dampener in 0..4 {
+                for policy in [
+                    SafetyPolicy {
+                        dampener,
+                        ..Default::default()
+                    },
+                    SafetyPolicy {
+                        min_step: 2,
+                        max_step: 4,
+                        direction: Direction::Decreasing,
+                        allow_equal: true,
+                        dampener,
+                    },
+                ] {
+                    let removed = policy.levels_to_remove(&seq);
+                    assert_eq!(
+                        removed.as_ref().map(|r| r.len()),
+                        brute_force(&policy, &seq),
+                        "{seq:?} {policy:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_example() {
+        let strict = SafetyPolicy::default();
+        assert_eq!(strict.levels_to_remove(&[7, 6, 4, 2, 1]), Some(vec![]));
+        let dampened = SafetyPolicy {
+            dampener: 1,
+            ..Default::default()
+        };
+        assert_eq!(dampened.levels_to_remove(&[1, 2, 7, 8, 9]), None);
+        assert_eq!(dampened.levels_to_remove(&[1, 3, 2, 4, 5]), Some(vec![2]));
+        assert_eq!(dampened.levels_to_remove(&[8, 6, 4, 4, 1]), Some(vec![3]));
+    }
+
+    #[test]
+    fn test_overrides() {
+        let args: Vec<String> = ["02b", "--max-step", "5", "--direction", "increasing"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let policy = SafetyPolicy::default().with_args(&args).unwrap();
+        assert_eq!(policy.max_step, 5);
+        assert_eq!(policy.direction, Direction::Increasing);
+        assert!(policy.is_safe(&[1, 6, 7]));
+        assert!(!policy.is_safe(&[7, 6, 1]));
+
+        let args: Vec<String> = ["02b", "--min-step", "4"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert!(SafetyPolicy::default().with_args(&args).is_err());
+    }
+
+    #[test]
+    fn test_diagnose() {
+        let policy = SafetyPolicy::default();
+        assert_eq!(policy.diagnose(1, &[7, 6, 4, 2, 1]), None);
+        let diagnosis = policy.diagnose(2, &[1, 2, 7, 8, 9]).unwrap();
+        assert_eq!(
+            (diagnosis.index, diagnosis.violation),
+            (2, Violation::StepTooLarge)
+        );
+        assert_eq!(diagnosis.fix, Some(vec![0, 1]));
+        let diagnosis = policy.diagnose(4, &[1, 3, 2, 4, 5]).unwrap();
+        assert_eq!(
+            (diagnosis.index, diagnosis.violation),
+            (2, Violation::DirectionFlip)
+        );
+        assert_eq!(diagnosis.fix, Some(vec![2]));
+        let diagnosis = policy.diagnose(5, &[8, 6, 4, 4, 1]).unwrap();
+        assert_eq!(
+            (diagnosis.index, diagnosis.violation),
+            (3, Violation::Plateau)
+        );
+        let diagnosis = policy.diagnose(6, &[5, 5]).unwrap();
+        assert_eq!(diagnosis.fix, None);
+    }
+}
@@ -0,0 +1,330 @@
+// Advent of Code 2024
+// (c) 2024 Mateusz Kwapich
+
+use crate::map::{Map, Point};
+use aho_corasick::AhoCorasick;
+use anyhow::Result;
+use std::collections::HashSet;
+
+/// One of the eight directions a word can be read in, with `N` pointing to
+/// the first row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Heading {
+    N,
+    NE,
+    E,
+    SE,
+    S,
+    SW,
+    W,
+    NW,
+}
+
+impl Heading {
+    pub const ALL: [Heading; 8] = [
+        Heading::N,
+        Heading::NE,
+        Heading::E,
+        Heading::SE,
+        Heading::S,
+        Heading::SW,
+        Heading::W,
+        Heading::NW,
+    ];
+
+    pub fn delta(self) -> (i64, i64) {
+        match self {
+            Heading::N => (0, -1),
+            Heading::NE => (1, -1),
+            Heading::E => (1, 0),
+            Heading::SE => (1, 1),
+            Heading::S => (0, 1),
+            Heading::SW => (-1, 1),
+            Heading::W => (-1, 0),
+            Heading::NW => (-1, -1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct WordMatch {
+    /// Position of the first letter.
+    pub start: Point,
+    pub heading: Heading,
+}
+
+fn word_at(map: &Map, word: &[u8], start: Point, heading: Heading) -> bool {
+    let (dx, dy) = heading.delta();
+    word.iter().enumerate().all(|(i, c)| {
+        let p = Point::new(start.x + dx * i as i64, start.y + dy * i as i64);
+        map.get(&p) == Some(*c)
+    })
+}
+
+/// Every occurence of the word in any of the eight directions. Palindromes
+/// are found twice at the same place, once for each direction.
+pub fn find_word(map: &Map, word: &[u8]) -> Vec<WordMatch> {
+    let Some(first) = word.first() else {
+        return vec![];
+    };
+    let mut matches = vec![];
+    for (start, c) in map.all_points() {
+        if c != *first {
+            continue;
+        }
+        for heading in Heading::ALL {
+            if word_at(map, word, start, heading) {
+                matches.push(WordMatch { start, heading });
+            }
+        }
+    }
+    matches
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Orientation {
+    /// Clockwise rotation in degrees.
+    pub rotation: u16,
+    /// Whether the pattern was mirrored left to right before rotating.
+    pub mirrored: bool,
+}
+
+/// Rectangular 2D pattern in which `None` matches any tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stencil {
+    rows: Vec<Vec<Option<u8>>>,
+}
+
+impl Stencil {
+    /// One row per line, with `wildcard` matching any tile.
+    pub fn parse(text: &str, wildcard: u8) -> Self {
+        let rows = text
+            .lines()
+            .map(|line| {
+                line.bytes()
+                    .map(|c| if c == wildcard { None } else { Some(c) })
+                    .collect()
+            })
+            .collect();
+        Stencil { rows }
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn width(&self) -> usize {
+        self.rows.iter().map(|row| row.len()).max().unwrap_or(0)
+    }
+
+    fn cell(&self, x: usize, y: usize) -> Option<u8> {
+        self.rows
+            .get(y)
+            .and_then(|row| row.get(x).copied().flatten())
+    }
+
+    fn rotate_clockwise(&self) -> Self {
+        let (w, h) = (self.width(), self.height());
+        let rows = (0..w)
+            .map(|y| (0..h).map(|x| self.cell(y, h - 1 - x)).collect())
+            .collect();
+        Stencil { rows }
+    }
+
+    fn mirror(&self) -> Self {
+        let w = self.width();
+        let rows = (0..self.height())
+            .map(|y| (0..w).map(|x| self.cell(w - 1 - x, y)).collect())
+            .collect();
+        Stencil { rows }
+    }
+
+    /// Distinct variants of the pattern, so that symmetric patterns don't
+    /// match twice at the same place.
+    pub fn orientations(&self, reflections: bool) -> Vec<(Orientation, Stencil)> {
+        let mut variants: Vec<(Orientation, Stencil)> = vec![];
+        for mirrored in [false, true] {
+            if mirrored && !reflections {
+                continue;
+            }
+            let mut stencil = if mirrored {
+                self.mirror()
+            } else {
+                self.clone()
+            };
+            for rotation in [0, 90, 180, 270] {
+                if !variants.iter().any(|(_o, s)| *s == stencil) {
+                    variants.push((Orientation { rotation, mirrored }, stencil.clone()));
+                }
+                stencil = stencil.rotate_clockwise();
+            }
+        }
+        variants
+    }
+
+    fn matches_at(&self, map: &Map, top_left: Point) -> bool {
+        self.rows.iter().enumerate().all(|(y, row)| {
+            row.iter().enumerate().all(|(x, c)| {
+                let p = Point::new(top_left.x + x as i64, top_left.y + y as i64);
+                match c {
+                    Some(c) => map.get(&p) == Some(*c),
+                    None => map.contains(&p),
+                }
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StencilMatch {
+    pub top_left: Point,
+    pub orientation: Orientation,
+}
+
+/// Every placement of the pattern, rotated (and optionally mirrored), which
+/// fits the map.
+pub fn find_stencil(map: &Map, stencil: &Stencil, reflections: bool) -> Vec<StencilMatch> {
+    let variants = stencil.orientations(reflections);
+    let mut matches = vec![];
+    for (top_left, _c) in map.all_points() {
+        for (orientation, variant) in variants.iter() {
+            if variant.matches_at(map, top_left) {
+                matches.push(StencilMatch {
+                    top_left,
+                    orientation: *orientation,
+                });
+            }
+        }
+    }
+    matches
+}
+
+/// Searches for a whole list of words at once, running one Aho-Corasick
+/// automaton over every row, column and diagonal in both directions.
+#[derive(Debug, Clone)]
+pub struct Dictionary {
+    words: Vec<String>,
+    automaton: AhoCorasick,
+}
+
+impl Dictionary {
+    /// Duplicate and empty words are dropped.
+    pub fn new<I, S>(words: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut seen = HashSet::new();
+        let mut unique: Vec<String> = vec![];
+        for word in words {
+            let word = word.into();
+            if !word.is_empty() && seen.insert(word.clone()) {
+                unique.push(word);
+            }
+        }
+        let automaton = AhoCorasick::new(&unique)?;
+        Ok(Dictionary {
+            words: unique,
+            automaton,
+        })
+    }
+
+    pub fn words(&self) -> &[String] {
+        &self.words
+    }
+
+    /// Matches of each word, in the same order as `words`.
+    pub fn search(&self, map: &Map) -> Vec<Vec<WordMatch>> {
+        let mut matches = vec![vec![]; self.words.len()];
+        let mut line = vec![];
+        let mut points = vec![];
+        for heading in Heading::ALL {
+            let (dx, dy) = heading.delta();
+            // every line starts at a tile which can't be reached by stepping
+            // forward from another one
+            for (start, _c) in map.all_points() {
+                if map.contains(&Point::new(start.x - dx, start.y - dy)) {
+                    continue;
+                }
+                line.clear();
+                points.clear();
+                let mut p = start;
+                while let Some(c) = map.get(&p) {
+                    line.push(c);
+                    points.push(p);
+                    p = Point::new(p.x + dx, p.y + dy);
+                }
+                for m in self.automaton.find_overlapping_iter(&line) {
+                    matches[m.pattern().as_usize()].push(WordMatch {
+                        start: points[m.start()],
+                        heading,
+                    });
+                }
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_word() {
+        let map = Map::parse("XMAS\nMM..\nA.A.\nS..S");
+        let mut matches = find_word(&map, b"XMAS");
+        matches.sort();
+        let start = Point::new(0, 0);
+        assert_eq!(
+            matches,
+            vec![
+                WordMatch {
+                    start,
+                    heading: Heading::E
+                },
+                WordMatch {
+                    start,
+                    heading: Heading::SE
+                },
+                WordMatch {
+                    start,
+                    heading: Heading::S
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stencil_orientations() {
+        let x_mas = Stencil::parse("M.S\n.A.\nM.S", b'.');
+        assert_eq!(x_mas.orientations(true).len(), 4);
+        let plus = Stencil::parse(".A.\nAAA\n.A.", b'.');
+        assert_eq!(plus.orientations(true).len(), 1);
+
+        let map = Map::parse("S.S\n.A.\nM.M");
+        assert_eq!(
+            find_stencil(&map, &x_mas, false),
+            vec![StencilMatch {
+                top_left: Point::new(0, 0),
+                orientation: Orientation {
+                    rotation: 270,
+                    mirrored: false
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn test_dictionary_matches_find_word() {
+        let map = Map::parse("XMASAMX\nMM..MMA\nA.A.A.M\nS..SS.X");
+        let dictionary = Dictionary::new(["XMAS", "AS", "MAS", "XMAS", "SAMX"]).unwrap();
+        assert_eq!(dictionary.words(), ["XMAS", "AS", "MAS", "SAMX"]);
+        for (word, mut matches) in dictionary.words().iter().zip(dictionary.search(&map)) {
+            let mut expected = find_word(&map, word.as_bytes());
+            expected.sort();
+            matches.sort();
+            assert_eq!(matches, expected, "{word}");
+        }
+    }
+}